@@ -6,28 +6,15 @@
 //# SPDX-License-Identifier: BSD-2-Clause or GPL-2.0-only
 
 use argh::FromArgs;
-use minijinja as jinja;
-use sel4_xml_types::invocations::*;
-use std::io::Read as _;
-use std::io::Write as _;
+use sel4_gen::invocations::{run, Options};
+use sel4_gen::{Error, Language};
+use sel4_xml_types::invocation_table::Layer;
 use std::path::PathBuf;
-use std::{fs, io};
-use thiserror::Error;
 
-mod lang_c;
-mod lang_rust;
-
-#[derive(Error, Debug)]
-enum Error {
-    #[error("Io Error: {0}")]
-    Io(#[from] io::Error),
-    #[error("MiniJinja Error: {0}")]
-    Minijinja(#[from] jinja::Error),
-    #[error("RoXmlTree Error: {0}")]
-    RoXmlTree(#[from] roxmltree::Error),
-    #[error("seL4_xml_types error: {0}")]
-    Sel4XmlTypes(#[from] sel4_xml_types::invocations::InvocationGenError),
-}
+//
+// Kept for the build systems which call it, sel4-gen invocations does the
+// same with --layer in place of -a and -s.
+//
 
 // argh isn't quite working out here:
 // It wants to rewrite sel4_arch to --sel4-arch
@@ -39,104 +26,77 @@ enum Error {
 #[derive(FromArgs, Debug)]
 struct TopArgs {
     /// rust | c       default: [rust]
-    #[argh(option, from_str_fn(language_arg))]
+    #[argh(option)]
     lang: Language,
     /// libsel4
     #[argh(switch, short = 'l')]
     libsel4: bool,
-    /// arch
+    /// arch_invocation_label, from arch_include/*/interfaces/sel4arch.xml
     #[argh(switch, short = 'a')]
     arch: bool,
-    /// sel4_arch
+    /// sel4_arch_invocation_label, from sel4_arch_include/*/interfaces/sel4arch.xml
+    /// without either switch invocation_label, from sel4.xml
     #[argh(switch, short = 's')]
     sel4_arch: bool,
     /// xml file...
     #[argh(option)]
-    xml: PathBuf,
+    xml: Option<PathBuf>,
     /// output file.
     #[argh(option)]
-    dest: PathBuf,
-}
-
-#[derive(Debug)]
-enum Language {
-    C,
-    Rust,
-}
-
-#[allow(clippy::if_same_then_else)]
-fn language_arg(s: &str) -> Result<Language, String> {
-    match s.to_lowercase() {
-        lang if lang == "rust" => Ok(Language::Rust),
-        lang if lang == "c" => Ok(Language::C),
-        dont_know => Err(format!("Unrecognized language '{}'", dont_know)),
-    }
+    dest: Option<PathBuf>,
+    /// seL4 checkout, generates the kernel and libsel4 headers of every
+    /// architecture in it into --out-dir, rather than --xml into --dest.
+    #[argh(option)]
+    kernel_tree: Option<PathBuf>,
+    /// directory the headers of --kernel-tree are written to, laid out as
+    /// in the seL4 build directory under a directory per sel4_arch.
+    #[argh(option)]
+    out_dir: Option<PathBuf>,
+    /// file of `CONDITION => PREDICATE` lines overriding how
+    /// conditions are rendered as rust cfg predicates.
+    #[argh(option)]
+    cfg_map: Option<PathBuf>,
+    /// check the xml against sel4_idl.dtd, ordering and attributes included.
+    #[argh(switch)]
+    validate: bool,
+    /// kernel build directory, gen_config.h, gen_config.json or CMakeCache.txt
+    /// only the invocations which exist in that configuration are generated,
+    /// not with --kernel-tree.
+    #[argh(option)]
+    config: Option<PathBuf>,
+    /// make style dependency file listing the xml and templates
+    /// each output was generated from.
+    #[argh(option)]
+    depfile: Option<PathBuf>,
 }
 
+#[allow(clippy::result_large_err)]
 fn main() -> Result<(), Error> {
     let args: TopArgs = argh::from_env();
-    let mut dest_file = fs::File::create(args.dest)?;
-    let mut env = jinja::Environment::new();
-
-    // So we catch any template parsing errors early
-    // add all of them to the environment whether they get used or not.
-    {
-        env.add_template("C_invocation", lang_c::INVOCATION_TEMPLATE)?;
-        env.add_template(
-            "C_sel4_arch_invocation",
-            lang_c::SEL4_ARCH_INVOCATION_TEMPLATE,
-        )?;
-        env.add_template("C_arch_invocation", lang_c::ARCH_INVOCATION_TEMPLATE)?;
-        env.add_template("Rust_invocation", lang_rust::INVOCATION_TEMPLATE)?;
-        env.add_template(
-            "Rust_sel4_arch_invocation",
-            lang_rust::SEL4_ARCH_INVOCATION_TEMPLATE,
-        )?;
-        env.add_template("Rust_arch_invocation", lang_rust::ARCH_INVOCATION_TEMPLATE)?;
-        let _ = env.get_template("C_invocation")?;
-        let _ = env.get_template("C_sel4_arch_invocation")?;
-        let _ = env.get_template("C_arch_invocation")?;
-
-        let _ = env.get_template("Rust_invocation")?;
-        let _ = env.get_template("Rust_sel4_arch_invocation")?;
-        let _ = env.get_template("Rust_arch_invocation")?;
+    // The layers and libsel4 of --kernel-tree are all generated.
+    if args.kernel_tree.is_some() && (args.arch || args.sel4_arch || args.libsel4) {
+        return Err(Error::Usage(
+            "-a, -s and -l can't be used with --kernel-tree",
+        ));
     }
-
-    let xml_in = fs::File::open(args.xml)?;
-    let mut reader = io::BufReader::new(xml_in);
-    let mut s = String::new();
-    let _len = reader.read_to_string(&mut s)?;
-    let xml_tree = roxmltree::Document::parse(&s)?;
-    let api: Api = Api::try_from(xml_tree)?;
-    let header_title = if args.libsel4 { "LIBSEL4" } else { "API" };
-    let lang = if let Language::C = args.lang {
-        "C"
-    } else {
-        "Rust"
+    let layer = match (args.arch, args.sel4_arch) {
+        (false, false) => Layer::Generic,
+        (true, false) => Layer::Arch,
+        (false, true) => Layer::Sel4Arch,
+        (true, true) => return Err(Error::Usage("-a and -s can't be used together")),
     };
-
-    let mut invocation_list = Vec::new();
-    for child in api.children {
-        if let ApiElement::Interface { methods, .. } = child {
-            for Method { id, condition, .. } in methods {
-                invocation_list.push((id, condition));
-            }
-        }
-    }
-
-    let ctx = jinja::context!(
-            libsel4 => args.libsel4,
-            header_title => header_title,
-            invocations => invocation_list,
-    );
-
-    let template = if args.arch {
-        env.get_template(&format!("{lang}_invocation"))
-    } else if args.sel4_arch {
-        env.get_template(&format!("{lang}_sel4_arch_invocation"))
-    } else {
-        env.get_template(&format!("{lang}_arch_invocation"))
-    }?;
-    dest_file.write_all(template.render(ctx)?.as_bytes())?;
-    Ok(())
+    run(&Options {
+        lang: args.lang,
+        libsel4: args.libsel4,
+        layer,
+        xml: args.xml,
+        dest: args.dest,
+        kernel_tree: args.kernel_tree,
+        out_dir: args.out_dir,
+        cfg_map: args.cfg_map,
+        validate: args.validate,
+        config: args.config,
+        template_dir: None,
+        depfile: args.depfile,
+    })
 }
@@ -0,0 +1,83 @@
+// Derived from syscall_stub_gen.py and licensed the same
+//#
+//# Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//#
+//# SPDX-License-Identifier: BSD-2-Clause
+//#
+
+use argh::FromArgs;
+use sel4_gen::stubs::{run, Options};
+use sel4_gen::{Error, Language};
+use sel4_xml_types::arch::Sel4Arch;
+use std::path::PathBuf;
+
+//
+// Kept for the build systems which call it, sel4-gen stubs does the same
+// and can also find the interfaces of a sel4_arch in a kernel tree.
+//
+
+/// gen_stubs
+#[derive(FromArgs, Debug)]
+struct TopArgs {
+    /// rust | c       default: [c]
+    #[argh(option, default = "Language::C")]
+    lang: Language,
+    /// aarch32 | arm_hyp | aarch64 | ia32 | x86_64 | riscv32 | riscv64
+    #[argh(option, from_str_fn(sel4_arch_arg))]
+    sel4_arch: Sel4Arch,
+    /// generate stubs for the MCS kernel.
+    #[argh(switch, short = 'm')]
+    mcs: bool,
+    /// pass every argument in the ipc buffer rather than in registers.
+    #[argh(switch, short = 'b')]
+    buffer: bool,
+    /// libsel4/include/interfaces/sel4.xml
+    #[argh(option)]
+    xml: PathBuf,
+    /// libsel4/sel4_arch_include/$SEL4_ARCH/interfaces/sel4arch.xml
+    #[argh(option)]
+    sel4_arch_xml: Option<PathBuf>,
+    /// libsel4/arch_include/$ARCH/interfaces/sel4arch.xml
+    #[argh(option)]
+    arch_xml: Option<PathBuf>,
+    /// output file.
+    #[argh(option)]
+    dest: PathBuf,
+    /// file of `CONDITION => PREDICATE` lines overriding how
+    /// conditions are rendered as rust cfg predicates.
+    #[argh(option)]
+    cfg_map: Option<PathBuf>,
+    /// check the xml against sel4_idl.dtd, ordering and attributes included.
+    #[argh(switch)]
+    validate: bool,
+    /// labels of the manual, from its LaTeX, its .aux file or one per line.
+    /// autorefs and shortrefs to any other label are errors.
+    #[argh(option)]
+    labels: Option<PathBuf>,
+}
+
+fn sel4_arch_arg(s: &str) -> Result<Sel4Arch, String> {
+    s.to_lowercase().parse()
+}
+
+#[allow(clippy::result_large_err)]
+fn main() -> Result<(), Error> {
+    let args: TopArgs = argh::from_env();
+    run(&Options {
+        lang: args.lang,
+        sel4_arch: args.sel4_arch,
+        mcs: args.mcs,
+        buffer: args.buffer,
+        xml: Some(args.xml),
+        sel4_arch_xml: args.sel4_arch_xml,
+        arch_xml: args.arch_xml,
+        kernel_tree: None,
+        dest: Some(args.dest),
+        out_dir: None,
+        cfg_map: args.cfg_map,
+        validate: args.validate,
+        labels: args.labels,
+        config: None,
+        template_dir: None,
+    })
+}
@@ -0,0 +1,100 @@
+use crate::{load_config, read, Error};
+use sel4_xml_types::diagnostic::Diagnostic;
+use sel4_xml_types::invocations::Api;
+use sel4_xml_types::kernel_tree::KernelTree;
+use sel4_xml_types::syscalls::Syscalls;
+use sel4_xml_types::xref::LabelIndex;
+use std::path::PathBuf;
+
+//
+// Checks the xml without generating anything, for CI and editors:
+// each IDL file is validated against sel4_idl.dtd and syscall.xml is
+// parsed, every error of every file is reported rather than the first.
+//
+
+/// The IDL files `xml` and `syscall_xml`, or those of `kernel_tree`.
+///
+/// With `labels` references to labels the manual doesn't have are errors,
+/// with `config` conditions which don't evaluate in that configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub xml: Vec<PathBuf>,
+    pub syscall_xml: Option<PathBuf>,
+    pub kernel_tree: Option<PathBuf>,
+    pub labels: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+}
+
+/// Emits the diagnostics of every file, and returns how many had errors.
+#[allow(clippy::result_large_err)]
+pub fn run(options: &Options) -> Result<usize, Error> {
+    let (interfaces, syscall_xml) = match (options.xml.as_slice(), &options.kernel_tree) {
+        (xml, None) if !xml.is_empty() || options.syscall_xml.is_some() => {
+            (xml.to_vec(), options.syscall_xml.clone())
+        }
+        ([], Some(kernel_tree)) if options.syscall_xml.is_none() => {
+            let tree = KernelTree::discover(kernel_tree)?;
+            let interfaces = tree.interface_files().into_iter().map(PathBuf::from);
+            (interfaces.collect(), Some(tree.syscall_xml.clone()))
+        }
+        _ => {
+            return Err(Error::Usage(
+                "either --xml and --syscall-xml or --kernel-tree is required",
+            ))
+        }
+    };
+    let labels = match &options.labels {
+        Some(path) => Some(LabelIndex::parse(&read(path)?)),
+        None => None,
+    };
+    let config = load_config(options.config.as_deref())?;
+
+    let mut failed = 0;
+    for xml in &interfaces {
+        let s = read(xml)?;
+        let mut diagnostics = Vec::new();
+        match Api::parse_validated(xml, &s) {
+            Ok((api, errors)) => {
+                for err in &errors {
+                    diagnostics.push(Diagnostic::from_sel4_xml_error(err, &s));
+                }
+                if let Some(labels) = &labels {
+                    for err in &labels.resolve(&api) {
+                        diagnostics.push(Diagnostic::from_dangling_ref(err, &s, labels));
+                    }
+                }
+                if let Some(config) = &config {
+                    if let Err(err) = api.configure(config, 0) {
+                        diagnostics.push(Diagnostic::new(err.to_string()));
+                    }
+                }
+            }
+            Err(err) => diagnostics.push(Diagnostic::from_sel4_xml_error(&err, &s)),
+        }
+        for diagnostic in &diagnostics {
+            diagnostic.emit(xml, &s)?;
+        }
+        if !diagnostics.is_empty() {
+            failed += 1;
+        }
+    }
+
+    if let Some(xml) = &syscall_xml {
+        let s = read(xml)?;
+        let diagnostic = match Syscalls::parse(xml, &s) {
+            Ok(syscalls) => match &config {
+                Some(config) => syscalls
+                    .configure(config)
+                    .err()
+                    .map(|err| Diagnostic::new(err.to_string())),
+                None => None,
+            },
+            Err(err) => Some(Diagnostic::from_sel4_xml_error(&err, &s)),
+        };
+        if let Some(diagnostic) = diagnostic {
+            diagnostic.emit(xml, &s)?;
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
@@ -0,0 +1,98 @@
+use crate::{write, Error};
+use std::path::{Path, PathBuf};
+
+//
+// Make style dependency files, so that a build using
+// `add_custom_command(DEPFILE ...)` or a ninja `depfile =` regenerates an
+// output when an IDL file or a template it was generated from changes:
+//
+//   generated/api/invocation.h: libsel4/include/interfaces/sel4.xml
+//
+// A rule per output, listing everything read to generate it.
+//
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depfile {
+    rules: Vec<(PathBuf, Vec<PathBuf>)>,
+}
+
+impl Depfile {
+    pub fn new() -> Depfile {
+        Depfile::default()
+    }
+
+    /// Records that `output` was generated from `inputs`.
+    pub fn add<I, P>(&mut self, output: &Path, inputs: I)
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut deps: Vec<PathBuf> = Vec::new();
+        for input in inputs {
+            let input = input.as_ref();
+            if !deps.iter().any(|dep| dep == input) {
+                deps.push(input.to_path_buf());
+            }
+        }
+        self.rules.push((output.to_path_buf(), deps));
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for (output, inputs) in &self.rules {
+            text.push_str(&escape(output));
+            text.push(':');
+            for input in inputs {
+                text.push(' ');
+                text.push_str(&escape(input));
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        write(path, &self.render())
+    }
+}
+
+// Spaces separate paths and `#` starts a comment, `$` is doubled as make
+// would otherwise expand it.
+fn escape(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut depfile = Depfile::new();
+        depfile.add(
+            Path::new("out/invocation.h"),
+            ["sel4.xml", "templates/C_invocation.jinja", "sel4.xml"],
+        );
+        depfile.add(
+            Path::new("out dir/syscall.h"),
+            [Path::new("my #1/$syscall.xml")],
+        );
+        assert_eq!(
+            depfile.render(),
+            "out/invocation.h: sel4.xml templates/C_invocation.jinja\n\
+             out\\ dir/syscall.h: my\\ \\#1/$$syscall.xml\n"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use crate::stubs::{DocFormat, Markdown};
+use crate::{load_config, parse_api, write, Error, Templates};
+use minijinja as jinja;
+use sel4_xml_types::invocations::{Api, ApiElement};
+use sel4_xml_types::kernel_tree::KernelTree;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+//
+// A Markdown reference of the invocations, one page per IDL file:
+//
+//   # the api
+//   ## each interface, with its cap description
+//   ### each method, with its brief and description,
+//       a table of its params and what it returns
+//
+// The documentation leaves are written the same way as in the doc comments
+// of the rust stubs.
+//
+
+const DOCS_TEMPLATE: &str = r#"# {{ title }}
+{%- for interface in interfaces %}
+
+## {{ interface.name }}
+{%- if interface.cap_desc %}
+
+{{ interface.cap_desc }}
+{%- endif %}
+{%- for method in interface.methods %}
+
+### {{ method.function }}
+{%- if method.condition %}
+
+Only with `{{ method.condition }}`.
+{%- endif %}
+{%- if method.brief %}
+
+{{ method.brief }}
+{%- endif %}
+{%- for line in method.description %}
+{%- if loop.first %}
+{% endif %}
+{{ line }}
+{%- endfor %}
+{%- if method.params %}
+
+| Type | Name | Direction | Description |
+|------|------|-----------|-------------|
+{%- for param in method.params %}
+| `{{ param.typ }}` | `{{ param.name }}` | {{ param.dir }} | {{ param.description }} |
+{%- endfor %}
+{%- endif %}
+{%- if method.returns %}
+
+Returns: {{ method.returns }}
+{%- endif %}
+{%- endfor %}
+{%- endfor %}
+"#;
+
+pub const TEMPLATES: &[(&str, &str)] = &[("Markdown_docs", DOCS_TEMPLATE)];
+
+/// A page per IDL file, of each `xml` or every interface file of
+/// `kernel_tree`, into `out_dir`. A single `xml` may be written to `dest`.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub xml: Vec<PathBuf>,
+    pub kernel_tree: Option<PathBuf>,
+    pub dest: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub validate: bool,
+    pub config: Option<PathBuf>,
+    pub template_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct DocParam {
+    typ: String,
+    name: String,
+    dir: String,
+    description: String,
+}
+
+#[derive(Serialize)]
+struct DocMethod {
+    function: String,
+    condition: Option<String>,
+    brief: String,
+    description: Vec<String>,
+    params: Vec<DocParam>,
+    returns: String,
+}
+
+#[derive(Serialize)]
+struct DocInterface {
+    name: String,
+    cap_desc: Option<String>,
+    methods: Vec<DocMethod>,
+}
+
+fn interfaces(api: &Api) -> Vec<DocInterface> {
+    api.children
+        .iter()
+        .filter_map(|child| match child {
+            ApiElement::Interface {
+                name,
+                cap_desc,
+                methods,
+                ..
+            } => Some(DocInterface {
+                name: name.clone(),
+                cap_desc: cap_desc.clone(),
+                methods: methods
+                    .iter()
+                    .map(|method| DocMethod {
+                        function: format!("{}_{}", name, method.name),
+                        condition: method.condition.clone(),
+                        brief: Markdown.line(&method.brief),
+                        description: Markdown.lines(&method.description),
+                        params: method
+                            .params
+                            .iter()
+                            .map(|param| DocParam {
+                                typ: param.typ().to_string(),
+                                name: param.name().to_string(),
+                                dir: param.dir().to_string(),
+                                description: Markdown.line(param.description()),
+                            })
+                            .collect(),
+                        returns: Markdown.return_line(&method.return_value),
+                    })
+                    .collect(),
+            }),
+            ApiElement::StructElem { .. } => None,
+        })
+        .collect()
+}
+
+// The page of each file, for a kernel tree named after the directory
+// the file is in e.g. `x86_64.md` rather than after `sel4arch.xml`.
+#[allow(clippy::result_large_err)]
+fn pages(options: &Options) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let page = |xml: &Path, name: &str| -> Result<(PathBuf, PathBuf), Error> {
+        let dest = match (&options.dest, &options.out_dir) {
+            (Some(dest), None) => dest.clone(),
+            (None, Some(out_dir)) => out_dir.join(name).with_extension("md"),
+            _ => return Err(Error::Usage("either --dest or --out-dir is required")),
+        };
+        Ok((xml.to_path_buf(), dest))
+    };
+    match (options.xml.as_slice(), &options.kernel_tree) {
+        ([], Some(kernel_tree)) if options.dest.is_none() => {
+            let tree = KernelTree::discover(kernel_tree)?;
+            let mut pages = vec![page(&tree.sel4_xml, "sel4")?];
+            for arch in &tree.arches {
+                pages.push(page(&arch.interfaces, arch.arch.name())?);
+                for sel4_arch in &arch.sel4_arches {
+                    pages.push(page(&sel4_arch.interfaces, sel4_arch.sel4_arch.name())?);
+                }
+            }
+            Ok(pages)
+        }
+        ([_, _, ..], None) if options.dest.is_some() => {
+            Err(Error::Usage("--dest takes a single --xml, use --out-dir"))
+        }
+        ([], None) => Err(Error::Usage("either --xml or --kernel-tree is required")),
+        (xml, None) => xml
+            .iter()
+            .map(|xml| {
+                let name = xml.file_stem().unwrap_or(xml.as_os_str());
+                page(xml, &name.to_string_lossy())
+            })
+            .collect(),
+        _ => Err(Error::Usage(
+            "either --xml or --kernel-tree and --out-dir are required",
+        )),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn run(options: &Options) -> Result<(), Error> {
+    let templates = Templates::load(TEMPLATES, options.template_dir.as_deref())?;
+    let env = templates.environment()?;
+    let config = load_config(options.config.as_deref())?;
+
+    for (xml, dest) in pages(options)? {
+        let (api, _) = parse_api(&xml, options.validate)?;
+        let api = match &config {
+            Some(config) => api.configure(config, 0)?.api,
+            None => api,
+        };
+        let title = match &api.name {
+            Some(name) => name.clone(),
+            None => xml.display().to_string(),
+        };
+        let ctx = jinja::context!(
+            title => title,
+            interfaces => interfaces(&api),
+        );
+        write(&dest, &env.get_template("Markdown_docs")?.render(ctx)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// Rust counterparts of the templates in lang_c.rs
+//# Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//#
+//# SPDX-License-Identifier: BSD-2-Clause or GPL-2.0-only
+//#
+//
+// The three enums chain their numbering the same way the C enums do,
+// so the generated files are expected to be `include!`d into one module.
+// Conditions are rendered into cfg predicates before reaching the templates.
+
+const COMMON_HEADER: &str = r#"//
+// Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//
+{%- if libsel4 %}
+// SPDX-License-Identifier: BSD-2-Clause
+{%- else %}
+// SPDX-License-Identifier: GPL-2.0-only
+{%- endif %}
+//
+
+// This file was generated by gen_invocations.
+//
+// To add an invocation call number, edit libsel4/include/interfaces/sel4.xml.
+//"#;
+
+pub const INVOCATION_TEMPLATE: &str = const_format::concatcp!(
+    COMMON_HEADER,
+    r#"
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(usize)]
+pub enum invocation_label {
+    InvalidInvocation,
+    {%- for label, condition in invocations %}
+    {%- if condition %}
+    #[cfg({{condition}})]
+    {%- endif %}
+    {{label}},
+    {%- endfor %}
+    nInvocationLabels,
+}
+
+"#
+);
+
+pub const SEL4_ARCH_INVOCATION_TEMPLATE: &str = const_format::concatcp!(
+    COMMON_HEADER,
+    r#"
+{%- for label, condition in invocations %}{% if loop.first and condition %}
+
+compile_error!("First sel4_arch invocation label cannot be conditional");
+{%- endif %}{% endfor %}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(usize)]
+pub enum sel4_arch_invocation_label {
+    {%- for label, condition in invocations %}
+        {%- if condition %}
+    #[cfg({{condition}})]
+        {%- endif %}
+        {%- if loop.first %}
+    {{label}} = invocation_label::nInvocationLabels as usize,
+        {%- else %}
+    {{label}},
+        {%- endif %}
+    {%- endfor %}
+    {%- if invocations|length == 0 %}
+    nSeL4ArchInvocationLabels = invocation_label::nInvocationLabels as usize,
+    {%- else %}
+    nSeL4ArchInvocationLabels,
+    {%- endif %}
+}
+
+"#
+);
+
+pub const ARCH_INVOCATION_TEMPLATE: &str = const_format::concatcp!(
+    COMMON_HEADER,
+    r#"
+{%- for label, condition in invocations %}{% if loop.first and condition %}
+
+compile_error!("First arch invocation label cannot be conditional");
+{%- endif %}{% endfor %}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(usize)]
+pub enum arch_invocation_label {
+    {%- for label, condition in invocations %}
+        {%- if condition %}
+    #[cfg({{condition}})]
+        {%- endif %}
+        {%- if loop.first %}
+    {{label}} = sel4_arch_invocation_label::nSeL4ArchInvocationLabels as usize,
+        {%- else %}
+    {{label}},
+        {%- endif %}
+    {%- endfor %}
+    {%- if invocations|length == 0 %}
+    nArchInvocationLabels = sel4_arch_invocation_label::nSeL4ArchInvocationLabels as usize,
+    {%- else %}
+    nArchInvocationLabels,
+    {%- endif %}
+}
+
+"#
+);
@@ -0,0 +1,217 @@
+// Derived from header_invocation_gen.py and licensed the same
+// Translation into rust 2022 Matt Rice
+//#
+//# Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//#
+//# SPDX-License-Identifier: BSD-2-Clause or GPL-2.0-only
+
+use crate::depfile::Depfile;
+use crate::{
+    config_file, depfile_inputs, load_cfg_map, load_config, parse_api, write, Error, Language,
+    Templates,
+};
+use minijinja as jinja;
+use sel4_xml_types::condition::{CfgMap, Condition};
+use sel4_xml_types::invocation_table::Layer;
+use sel4_xml_types::invocations::*;
+use sel4_xml_types::kernel_tree::{InterfaceFiles, KernelTree, KernelTreeError};
+use std::path::{Path, PathBuf};
+
+mod lang_c;
+mod lang_rust;
+
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("C_invocation", lang_c::INVOCATION_TEMPLATE),
+    (
+        "C_sel4_arch_invocation",
+        lang_c::SEL4_ARCH_INVOCATION_TEMPLATE,
+    ),
+    ("C_arch_invocation", lang_c::ARCH_INVOCATION_TEMPLATE),
+    ("Rust_invocation", lang_rust::INVOCATION_TEMPLATE),
+    (
+        "Rust_sel4_arch_invocation",
+        lang_rust::SEL4_ARCH_INVOCATION_TEMPLATE,
+    ),
+    ("Rust_arch_invocation", lang_rust::ARCH_INVOCATION_TEMPLATE),
+];
+
+/// The invocation label enums of either a single layer, `xml` into `dest`,
+/// or of every layer and sel4_arch of `kernel_tree` into `out_dir`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub lang: Language,
+    pub libsel4: bool,
+    pub layer: Layer,
+    pub xml: Option<PathBuf>,
+    pub dest: Option<PathBuf>,
+    pub kernel_tree: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub cfg_map: Option<PathBuf>,
+    pub validate: bool,
+    pub config: Option<PathBuf>,
+    pub template_dir: Option<PathBuf>,
+    /// Make style dependency file of every output written.
+    pub depfile: Option<PathBuf>,
+}
+
+// The templates of a layer e.g. `C_sel4_arch_invocation`
+fn template_name(lang: Language, layer: Layer) -> String {
+    let lang = lang.template_prefix();
+    match layer {
+        Layer::Generic => format!("{lang}_invocation"),
+        Layer::Sel4Arch => format!("{lang}_sel4_arch_invocation"),
+        Layer::Arch => format!("{lang}_arch_invocation"),
+    }
+}
+
+// Where the seL4 build writes the header of each layer, relative to its
+// build directory. libsel4 headers go into the libsel4 build directory.
+fn header_path(files: &InterfaceFiles, layer: Layer, libsel4: bool) -> PathBuf {
+    match (layer, libsel4) {
+        (Layer::Generic, false) => PathBuf::from("generated/api/invocation.h"),
+        (Layer::Sel4Arch, false) => PathBuf::from("generated/arch/api/sel4_invocation.h"),
+        (Layer::Arch, false) => PathBuf::from("generated/arch/api/invocation.h"),
+        (Layer::Generic, true) => PathBuf::from("libsel4/include/sel4/invocation.h"),
+        (Layer::Sel4Arch, true) => Path::new("libsel4/sel4_arch_include")
+            .join(files.sel4_arch.name())
+            .join("sel4/sel4_arch/invocation.h"),
+        (Layer::Arch, true) => Path::new("libsel4/arch_include")
+            .join(files.arch.name())
+            .join("sel4/arch/invocation.h"),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn render(
+    env: &jinja::Environment,
+    lang: Language,
+    cfg_map: &CfgMap,
+    layer: Layer,
+    libsel4: bool,
+    api: Api,
+) -> Result<String, Error> {
+    let header_title = if libsel4 { "LIBSEL4" } else { "API" };
+
+    let mut invocation_list = Vec::new();
+    for child in api.children {
+        if let ApiElement::Interface { methods, .. } = child {
+            for Method { id, condition, .. } in methods {
+                // The C templates paste conditions into `#if` as they are,
+                // rust needs them translated into cfg predicates.
+                let condition = match (lang, condition) {
+                    (Language::Rust, Some(condition)) => {
+                        Some(cfg_map.render(&condition.parse::<Condition>()?)?)
+                    }
+                    (_, condition) => condition,
+                };
+                invocation_list.push((id, condition));
+            }
+        }
+    }
+
+    let ctx = jinja::context!(
+            libsel4 => libsel4,
+            header_title => header_title,
+            invocations => invocation_list,
+    );
+
+    let template = env.get_template(&template_name(lang, layer))?;
+    Ok(template.render(ctx)?)
+}
+
+#[allow(clippy::result_large_err)]
+pub fn run(options: &Options) -> Result<(), Error> {
+    let templates = Templates::load(TEMPLATES, options.template_dir.as_deref())?;
+    let env = templates.environment()?;
+
+    let config = load_config(options.config.as_deref())?;
+    let configure = |api: Api| -> Result<Api, Error> {
+        Ok(match &config {
+            Some(config) => api.configure(config, 0)?.api,
+            None => api,
+        })
+    };
+    let parse = |xml: &Path| -> Result<Api, Error> {
+        let (api, _) = parse_api(xml, options.validate)?;
+        configure(api)
+    };
+    let cfg_map = load_cfg_map(options.cfg_map.as_deref())?;
+    let mut depfile = Depfile::new();
+    let config_file = config_file(options.config.as_deref())?;
+    // Only the rust templates render conditions with the cfg map.
+    let cfg_map_file = match options.lang {
+        Language::Rust => options.cfg_map.as_deref(),
+        Language::C => None,
+    };
+    let mut add_dep = |dest: &Path, xml: &Path, layer: Layer| {
+        let template = templates.file(&template_name(options.lang, layer));
+        let inputs = [xml].into_iter().chain(template);
+        depfile.add(
+            dest,
+            inputs.chain(depfile_inputs(cfg_map_file, config_file.as_deref())),
+        );
+    };
+
+    match (
+        &options.xml,
+        &options.dest,
+        &options.kernel_tree,
+        &options.out_dir,
+    ) {
+        (Some(xml), Some(dest), None, None) => {
+            let api = parse(xml)?;
+            let header = render(
+                &env,
+                options.lang,
+                &cfg_map,
+                options.layer,
+                options.libsel4,
+                api,
+            )?;
+            write(dest, &header)?;
+            add_dep(dest, xml, options.layer);
+        }
+        // A configuration is of a single sel4_arch, and the tree has them all.
+        (None, None, Some(_), Some(_)) if options.config.is_some() => {
+            return Err(Error::Usage("--config can't be used with --kernel-tree"))
+        }
+        (None, None, Some(kernel_tree), Some(out_dir)) => {
+            let tree = KernelTree::discover(kernel_tree)?;
+            for arch in &tree.arches {
+                for sel4_arch in &arch.sel4_arches {
+                    let files = tree
+                        .interfaces(sel4_arch.sel4_arch)
+                        .ok_or(KernelTreeError::MissingSel4Arch(sel4_arch.sel4_arch))?;
+                    for (layer, xml) in [
+                        (Layer::Generic, &files.sel4_xml),
+                        (Layer::Sel4Arch, &files.sel4_arch_xml),
+                        (Layer::Arch, &files.arch_xml),
+                    ] {
+                        let api = parse(xml)?;
+                        for libsel4 in [false, true] {
+                            let mut dest = out_dir
+                                .join(files.sel4_arch.name())
+                                .join(header_path(&files, layer, libsel4));
+                            if let Language::Rust = options.lang {
+                                dest.set_extension("rs");
+                            }
+                            let header =
+                                render(&env, options.lang, &cfg_map, layer, libsel4, api.clone())?;
+                            write(&dest, &header)?;
+                            add_dep(&dest, xml, layer);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(Error::Usage(
+                "either --xml and --dest or --kernel-tree and --out-dir are required",
+            ))
+        }
+    }
+    if let Some(path) = &options.depfile {
+        depfile.write(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use minijinja as jinja;
+use sel4_xml_types::condition::CfgMap;
+use sel4_xml_types::config::KernelConfig;
+use sel4_xml_types::diagnostic::Diagnostic;
+use sel4_xml_types::invocations::{Api, Sel4XmlError};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io, process};
+use thiserror::Error;
+
+//
+// The generators behind the sel4-gen command line.
+//
+// Each module takes an `Options` struct, filled in by sel4-gen or by the
+// gen_invocations, gen_syscalls and gen_stubs binaries kept for
+// compatibility, so that both behave the same.
+//
+
+pub mod check;
+pub mod depfile;
+pub mod docs;
+pub mod invocations;
+pub mod stubs;
+pub mod syscalls;
+mod test_support;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Io Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{}: {1}", .0.display())]
+    File(PathBuf, io::Error),
+    #[error("MiniJinja Error: {0}")]
+    Minijinja(#[from] jinja::Error),
+    #[error("seL4_xml_types error: {0}")]
+    Sel4XmlTypes(#[from] Sel4XmlError),
+    #[error("Kernel configuration Error: {0}")]
+    Config(#[from] sel4_xml_types::config::ConfigError),
+    #[error("Condition Error: {0}")]
+    Condition(#[from] sel4_xml_types::condition::ConditionError),
+    #[error("Kernel tree Error: {0}")]
+    KernelTree(#[from] sel4_xml_types::kernel_tree::KernelTreeError),
+    #[error("Stub Error: {0}")]
+    Stub(#[from] stubs::StubError),
+    #[error("Type Error: {0}")]
+    Type(#[from] sel4_xml_types::types::TypeError),
+    #[error("{0}")]
+    Usage(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Language {
+    #[default]
+    C,
+    Rust,
+}
+
+impl Language {
+    /// The prefix of the language's templates e.g. `C_stubs`
+    pub fn template_prefix(self) -> &'static str {
+        match self {
+            Language::C => "C",
+            Language::Rust => "Rust",
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Language, String> {
+        match s.to_lowercase() {
+            lang if lang == "rust" => Ok(Language::Rust),
+            lang if lang == "c" => Ok(Language::C),
+            dont_know => Err(format!("Unrecognized language '{}'", dont_know)),
+        }
+    }
+}
+
+/// The built-in templates of a generator, with those of a template
+/// directory in place of any of the same name e.g. `C_stubs.jinja`
+pub struct Templates {
+    builtin: &'static [(&'static str, &'static str)],
+    overrides: Vec<(&'static str, PathBuf, String)>,
+}
+
+impl Templates {
+    #[allow(clippy::result_large_err)]
+    pub fn load(
+        builtin: &'static [(&'static str, &'static str)],
+        dir: Option<&Path>,
+    ) -> Result<Templates, Error> {
+        let mut overrides = Vec::new();
+        if let Some(dir) = dir {
+            if !dir.is_dir() {
+                return Err(Error::File(
+                    dir.to_path_buf(),
+                    io::Error::new(io::ErrorKind::NotFound, "template directory not found"),
+                ));
+            }
+            for (name, _) in builtin {
+                let path = dir.join(format!("{}.jinja", name));
+                if path.is_file() {
+                    let source = read(&path)?;
+                    overrides.push((*name, path, source));
+                }
+            }
+        }
+        Ok(Templates { builtin, overrides })
+    }
+
+    // So we catch any template parsing errors early
+    // add all of them to the environment whether they get used or not.
+    #[allow(clippy::result_large_err)]
+    pub fn environment(&self) -> Result<jinja::Environment<'_>, Error> {
+        let mut env = jinja::Environment::new();
+        for (name, source) in self.builtin {
+            match self.overrides.iter().find(|(o, _, _)| o == name) {
+                Some((_, _, source)) => env.add_template(name, source)?,
+                None => env.add_template(name, source)?,
+            }
+        }
+        for (name, _) in self.builtin {
+            let _ = env.get_template(name)?;
+        }
+        Ok(env)
+    }
+
+    /// The file template `name` was read from, `None` if it is built in.
+    pub fn file(&self, name: &str) -> Option<&Path> {
+        self.overrides
+            .iter()
+            .find(|(o, _, _)| *o == name)
+            .map(|(_, path, _)| path.as_path())
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub(crate) fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|err| Error::File(path.to_path_buf(), err))
+}
+
+// Creates the directories leading up to `path`, as the output directory
+// mirrors the nested layout of the seL4 build.
+#[allow(clippy::result_large_err)]
+pub(crate) fn write(path: &Path, contents: &str) -> Result<(), Error> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|err| Error::File(parent.to_path_buf(), err))?;
+    }
+    fs::write(path, contents).map_err(|err| Error::File(path.to_path_buf(), err))
+}
+
+// Reports every error found in the xml, rather than just the first.
+pub(crate) fn exit_with_diagnostics<T>(
+    file: &Path,
+    source: &str,
+    errors: &[Sel4XmlError],
+) -> io::Result<T> {
+    for err in errors {
+        Diagnostic::from_sel4_xml_error(err, source).emit(file, source)?;
+    }
+    process::exit(1)
+}
+
+/// Parses an IDL file, exiting with diagnostics if it has any errors.
+#[allow(clippy::result_large_err)]
+pub(crate) fn parse_api(xml: &Path, validate: bool) -> Result<(Api, String), Error> {
+    let s = read(xml)?;
+    let parsed = if validate {
+        Api::parse_validated(xml, &s)
+    } else {
+        Api::parse_recovering(xml, &s)
+    };
+    let api = match parsed {
+        Ok((api, errors)) if errors.is_empty() => api,
+        Ok((_, errors)) => exit_with_diagnostics(xml, &s, &errors)?,
+        Err(err) => exit_with_diagnostics(xml, &s, &[err])?,
+    };
+    Ok((api, s))
+}
+
+// What an output depends on besides its xml and templates, `config` is the
+// file the kernel configuration was read from, see `config_file`.
+pub(crate) fn depfile_inputs<'a>(
+    cfg_map: Option<&'a Path>,
+    config: Option<&'a Path>,
+) -> impl Iterator<Item = &'a Path> {
+    cfg_map.into_iter().chain(config)
+}
+
+// The file within a build directory given as the kernel configuration.
+#[allow(clippy::result_large_err)]
+pub(crate) fn config_file(config: Option<&Path>) -> Result<Option<PathBuf>, Error> {
+    Ok(match config {
+        Some(config) => Some(KernelConfig::resolve(config)?),
+        None => None,
+    })
+}
+
+#[allow(clippy::result_large_err)]
+pub(crate) fn load_config(config: Option<&Path>) -> Result<Option<KernelConfig>, Error> {
+    Ok(match config {
+        Some(config) => Some(KernelConfig::load(config)?),
+        None => None,
+    })
+}
+
+#[allow(clippy::result_large_err)]
+pub(crate) fn load_cfg_map(cfg_map: Option<&Path>) -> Result<CfgMap, Error> {
+    let mut map = CfgMap::new();
+    if let Some(path) = cfg_map {
+        map.parse_overrides(&read(path)?)?;
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILTIN: &[(&str, &str)] = &[("C_a", "builtin a"), ("C_b", "builtin b")];
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn template_dir() -> Result<(), Error> {
+        let dir = std::env::temp_dir().join(format!("sel4_gen-{}", std::process::id()));
+        write(&dir.join("C_b.jinja"), "override {{ x }}")?;
+        write(&dir.join("C_c.jinja"), "not a builtin")?;
+        let rendered = Templates::load(BUILTIN, Some(&dir)).and_then(|templates| {
+            let env = templates.environment()?;
+            let a = env.get_template("C_a")?.render(())?;
+            let b = env.get_template("C_b")?.render(jinja::context!(x => 1))?;
+            Ok((a, b, env.get_template("C_c").is_err()))
+        });
+        write(&dir.join("C_a.jinja"), "{% for")?;
+        let broken = Templates::load(BUILTIN, Some(&dir)).and_then(|t| t.environment().map(drop));
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(
+            rendered?,
+            ("builtin a".to_string(), "override 1".to_string(), true)
+        );
+        assert!(matches!(broken, Err(Error::Minijinja(_))));
+        assert!(matches!(
+            Templates::load(BUILTIN, Some(&dir)),
+            Err(Error::File(..))
+        ));
+        Ok(())
+    }
+}
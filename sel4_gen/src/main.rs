@@ -0,0 +1,316 @@
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use sel4_gen::{check, docs, invocations, stubs, syscalls, Error, Language};
+use sel4_xml_types::arch::Sel4Arch;
+use sel4_xml_types::invocation_table::Layer;
+use std::path::PathBuf;
+use std::process;
+
+/// Generates the sources the seL4 kernel and libsel4 build from their xml.
+#[derive(Parser, Debug)]
+#[command(name = "sel4-gen", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Invocation label enums, from sel4.xml and the sel4arch.xml files.
+    Invocations(InvocationsArgs),
+    /// Syscall numbers, from syscall.xml.
+    Syscalls(SyscallsArgs),
+    /// The libsel4 invocation stubs of a sel4_arch.
+    Stubs(StubsArgs),
+    /// A Markdown reference of the invocations.
+    Docs(DocsArgs),
+    /// Checks the xml, reporting every error without generating anything.
+    Check(CheckArgs),
+}
+
+/// Options shared by the generators.
+#[derive(Args, Debug)]
+struct Common {
+    /// Directory the output is written to, laid out as in the seL4 build
+    /// directory.
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+    /// Kernel build directory, gen_config.h, gen_config.json or
+    /// CMakeCache.txt. Only what exists in that configuration is generated.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Directory of `<template>.jinja` files used in place of the built-in
+    /// templates of the same name e.g. `C_stubs.jinja`.
+    #[arg(long, value_name = "DIR")]
+    template_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LayerArg {
+    /// invocation_label, from sel4.xml
+    Generic,
+    /// sel4_arch_invocation_label, from sel4_arch_include/*/interfaces/sel4arch.xml
+    Sel4Arch,
+    /// arch_invocation_label, from arch_include/*/interfaces/sel4arch.xml
+    Arch,
+}
+
+impl From<LayerArg> for Layer {
+    fn from(layer: LayerArg) -> Layer {
+        match layer {
+            LayerArg::Generic => Layer::Generic,
+            LayerArg::Sel4Arch => Layer::Sel4Arch,
+            LayerArg::Arch => Layer::Arch,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("input").required(true).args(["xml", "kernel_tree"])))]
+struct InvocationsArgs {
+    /// Language of the generated source.
+    #[arg(long, value_enum, default_value_t = Language::C)]
+    lang: Language,
+    /// Generate the libsel4 header rather than the kernel's.
+    #[arg(short, long, conflicts_with = "kernel_tree")]
+    libsel4: bool,
+    /// The label enum `--xml` holds.
+    #[arg(long, value_enum, default_value_t = LayerArg::Generic, conflicts_with = "kernel_tree")]
+    layer: LayerArg,
+    /// IDL file to generate from.
+    #[arg(long, value_name = "FILE", requires = "dest")]
+    xml: Option<PathBuf>,
+    /// Output file of `--xml`.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "xml",
+        conflicts_with = "out_dir"
+    )]
+    dest: Option<PathBuf>,
+    /// seL4 checkout, generates the kernel and libsel4 headers of every
+    /// sel4_arch in it into a directory per sel4_arch of `--out-dir`.
+    #[arg(long, value_name = "DIR", requires = "out_dir")]
+    kernel_tree: Option<PathBuf>,
+    /// File of `CONDITION => PREDICATE` lines overriding how conditions are
+    /// rendered as rust cfg predicates.
+    #[arg(long, value_name = "FILE")]
+    cfg_map: Option<PathBuf>,
+    /// Check the xml against sel4_idl.dtd, ordering and attributes included.
+    #[arg(long)]
+    validate: bool,
+    /// Make style dependency file, listing the xml and templates each
+    /// output was generated from.
+    #[arg(long, value_name = "FILE")]
+    depfile: Option<PathBuf>,
+    #[command(flatten)]
+    common: Common,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("input").required(true).args(["xml", "kernel_tree"])))]
+#[command(group(
+    ArgGroup::new("output")
+        .required(true)
+        .multiple(true)
+        .args(["kernel_header", "libsel4_header", "rust_module", "out_dir"])
+))]
+struct SyscallsArgs {
+    /// Language of the outputs `--out-dir` gets.
+    #[arg(long, value_enum, default_value_t = Language::C, requires = "out_dir")]
+    lang: Language,
+    /// syscall.xml
+    #[arg(long, value_name = "FILE")]
+    xml: Option<PathBuf>,
+    /// seL4 checkout, the syscall.xml of which is generated from.
+    #[arg(long, value_name = "DIR")]
+    kernel_tree: Option<PathBuf>,
+    /// Kernel header output file.
+    #[arg(long, value_name = "FILE")]
+    kernel_header: Option<PathBuf>,
+    /// libsel4 header output file.
+    #[arg(long, value_name = "FILE")]
+    libsel4_header: Option<PathBuf>,
+    /// Rust module output file.
+    #[arg(long, value_name = "FILE")]
+    rust_module: Option<PathBuf>,
+    /// File of `CONDITION => PREDICATE` lines overriding how conditions are
+    /// rendered as rust cfg predicates.
+    #[arg(long, value_name = "FILE")]
+    cfg_map: Option<PathBuf>,
+    /// Generate the MCS api.
+    #[arg(short, long)]
+    mcs: bool,
+    /// Make style dependency file, listing the xml and templates each
+    /// output was generated from.
+    #[arg(long, value_name = "FILE")]
+    depfile: Option<PathBuf>,
+    #[command(flatten)]
+    common: Common,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("input").required(true).args(["xml", "kernel_tree"])))]
+#[command(group(ArgGroup::new("output").required(true).args(["dest", "out_dir"])))]
+struct StubsArgs {
+    /// Language of the generated source.
+    #[arg(long, value_enum, default_value_t = Language::C)]
+    lang: Language,
+    /// aarch32 | arm_hyp | aarch64 | ia32 | x86_64 | riscv32 | riscv64
+    #[arg(long)]
+    sel4_arch: Sel4Arch,
+    /// Generate stubs for the MCS kernel.
+    #[arg(short, long)]
+    mcs: bool,
+    /// Pass every argument in the ipc buffer rather than in registers.
+    #[arg(short, long)]
+    buffer: bool,
+    /// libsel4/include/interfaces/sel4.xml
+    #[arg(long, value_name = "FILE")]
+    xml: Option<PathBuf>,
+    /// libsel4/sel4_arch_include/$SEL4_ARCH/interfaces/sel4arch.xml
+    #[arg(long, value_name = "FILE", requires = "xml")]
+    sel4_arch_xml: Option<PathBuf>,
+    /// libsel4/arch_include/$ARCH/interfaces/sel4arch.xml
+    #[arg(long, value_name = "FILE", requires = "xml")]
+    arch_xml: Option<PathBuf>,
+    /// seL4 checkout, the interfaces of `--sel4-arch` in which are
+    /// generated from.
+    #[arg(long, value_name = "DIR")]
+    kernel_tree: Option<PathBuf>,
+    /// Output file.
+    #[arg(long, value_name = "FILE")]
+    dest: Option<PathBuf>,
+    /// File of `CONDITION => PREDICATE` lines overriding how conditions are
+    /// rendered as rust cfg predicates.
+    #[arg(long, value_name = "FILE")]
+    cfg_map: Option<PathBuf>,
+    /// Check the xml against sel4_idl.dtd, ordering and attributes included.
+    #[arg(long)]
+    validate: bool,
+    /// Labels of the manual, from its LaTeX, its .aux file or one per line.
+    /// autorefs and shortrefs to any other label are errors.
+    #[arg(long, value_name = "FILE")]
+    labels: Option<PathBuf>,
+    #[command(flatten)]
+    common: Common,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("input").required(true).args(["xml", "kernel_tree"])))]
+#[command(group(ArgGroup::new("output").required(true).args(["dest", "out_dir"])))]
+struct DocsArgs {
+    /// IDL files, a page each.
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    xml: Vec<PathBuf>,
+    /// seL4 checkout, a page per interface file in it.
+    #[arg(long, value_name = "DIR", conflicts_with = "dest")]
+    kernel_tree: Option<PathBuf>,
+    /// Output file of a single `--xml`.
+    #[arg(long, value_name = "FILE")]
+    dest: Option<PathBuf>,
+    /// Check the xml against sel4_idl.dtd, ordering and attributes included.
+    #[arg(long)]
+    validate: bool,
+    #[command(flatten)]
+    common: Common,
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("input")
+        .required(true)
+        .multiple(true)
+        .args(["xml", "syscall_xml", "kernel_tree"])
+))]
+struct CheckArgs {
+    /// IDL files.
+    #[arg(long, value_name = "FILE", num_args = 1.., conflicts_with = "kernel_tree")]
+    xml: Vec<PathBuf>,
+    /// syscall.xml
+    #[arg(long, value_name = "FILE", conflicts_with = "kernel_tree")]
+    syscall_xml: Option<PathBuf>,
+    /// seL4 checkout, every xml file in it is checked.
+    #[arg(long, value_name = "DIR")]
+    kernel_tree: Option<PathBuf>,
+    /// Labels of the manual, from its LaTeX, its .aux file or one per line.
+    /// autorefs and shortrefs to any other label are errors.
+    #[arg(long, value_name = "FILE")]
+    labels: Option<PathBuf>,
+    /// Kernel build directory, gen_config.h, gen_config.json or
+    /// CMakeCache.txt, conditions it can't evaluate are errors.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+}
+
+#[allow(clippy::result_large_err)]
+fn main() -> Result<(), Error> {
+    match Cli::parse().command {
+        Command::Invocations(args) => invocations::run(&invocations::Options {
+            lang: args.lang,
+            libsel4: args.libsel4,
+            layer: args.layer.into(),
+            xml: args.xml,
+            dest: args.dest,
+            kernel_tree: args.kernel_tree,
+            out_dir: args.common.out_dir,
+            cfg_map: args.cfg_map,
+            validate: args.validate,
+            config: args.common.config,
+            template_dir: args.common.template_dir,
+            depfile: args.depfile,
+        }),
+        Command::Syscalls(args) => syscalls::run(&syscalls::Options {
+            lang: args.lang,
+            xml: args.xml,
+            kernel_tree: args.kernel_tree,
+            kernel_header: args.kernel_header,
+            libsel4_header: args.libsel4_header,
+            rust_module: args.rust_module,
+            out_dir: args.common.out_dir,
+            cfg_map: args.cfg_map,
+            mcs: args.mcs,
+            config: args.common.config,
+            template_dir: args.common.template_dir,
+            depfile: args.depfile,
+        }),
+        Command::Stubs(args) => stubs::run(&stubs::Options {
+            lang: args.lang,
+            sel4_arch: args.sel4_arch,
+            mcs: args.mcs,
+            buffer: args.buffer,
+            xml: args.xml,
+            sel4_arch_xml: args.sel4_arch_xml,
+            arch_xml: args.arch_xml,
+            kernel_tree: args.kernel_tree,
+            dest: args.dest,
+            out_dir: args.common.out_dir,
+            cfg_map: args.cfg_map,
+            validate: args.validate,
+            labels: args.labels,
+            config: args.common.config,
+            template_dir: args.common.template_dir,
+        }),
+        Command::Docs(args) => docs::run(&docs::Options {
+            xml: args.xml,
+            kernel_tree: args.kernel_tree,
+            dest: args.dest,
+            out_dir: args.common.out_dir,
+            validate: args.validate,
+            config: args.common.config,
+            template_dir: args.common.template_dir,
+        }),
+        Command::Check(args) => {
+            let failed = check::run(&check::Options {
+                xml: args.xml,
+                syscall_xml: args.syscall_xml,
+                kernel_tree: args.kernel_tree,
+                labels: args.labels,
+                config: args.config,
+            })?;
+            if failed > 0 {
+                process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
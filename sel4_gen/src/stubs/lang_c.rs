@@ -0,0 +1,533 @@
+// Templates and expressions taken from sel4 syscall_stub_gen.py
+//# Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//#
+//# SPDX-License-Identifier: BSD-2-Clause
+//#
+
+use super::stub::{mask, Dir, DocFormat, Stub, StubParam};
+use sel4_xml_types::invocations::LeafNode;
+use sel4_xml_types::layout::{Piece, Slot};
+use sel4_xml_types::types::{TypeKind, TypeTable};
+use serde::Serialize;
+
+pub const STUBS_TEMPLATE: &str = r#"/*
+ * Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+ *
+ * SPDX-License-Identifier: BSD-2-Clause
+ */
+
+/*
+ * Automatically generated system call stubs.
+ */
+
+#pragma once
+{%- if buffer %}
+
+#define CONFIG_LIB_SEL4_HAVE_REGISTER_STUBS
+{%- endif %}
+
+#include <sel4/config.h>
+#include <sel4/types.h>
+
+/*
+ * The following code generates a compile-time error if the system call
+ * stub generator has an incorrect understanding of how large a type is.
+ *
+ * If you receive a compile-time error here, you will need to adjust
+ * the type information in the stub generator.
+ */
+#define assert_size_correct(type, expected_bytes) \
+        typedef unsigned long __type_##type##_size_incorrect[ \
+                (sizeof(type) == expected_bytes) ? 1 : -1]
+{% for name, bytes in type_sizes %}
+assert_size_correct({{name}}, {{bytes}});
+{%- endfor %}
+
+/*
+ * Return types for generated methods.
+ */
+{%- for stub in stubs %}
+{%- if stub.result_fields %}
+
+struct {{stub.name}} {
+    int error;
+{%- for field in stub.result_fields %}
+    {{field}};
+{%- endfor %}
+};
+typedef struct {{stub.name}} {{stub.name}}_t;
+{%- endif %}
+{%- endfor %}
+
+/*
+ * Generated stubs.
+ */
+{%- for stub in stubs %}
+{%- if stub.condition %}
+#if {{stub.condition}}
+{%- endif %}
+/**
+{%- for line in stub.comment %}
+ *{% if line %} {{line}}{% endif %}
+{%- endfor %}
+ */
+LIBSEL4_INLINE {{stub.return_type}}
+{{stub.name}}({{stub.params}})
+{
+    {{stub.return_type}} result;
+    seL4_MessageInfo_t tag = seL4_MessageInfo_new({{stub.label}}, 0, {{stub.extra_caps | length}}, {{stub.length}});
+    seL4_MessageInfo_t output_tag;
+{%- for mr in stub.mrs %}
+    seL4_Word mr{{loop.index0}};
+{%- endfor %}
+{%- if stub.extra_caps %}
+
+    /* Setup input capabilities. */
+{%- for cap in stub.extra_caps %}
+    seL4_SetCap({{loop.index0}}, {{cap}});
+{%- endfor %}
+{%- endif %}
+{%- if stub.mrs or stub.buffered %}
+
+    /* Marshal and initialise parameters. */
+{%- for mr in stub.mrs %}
+    mr{{loop.index0}} = {{mr}};
+{%- endfor %}
+{%- for index, expr in stub.buffered %}
+    seL4_SetMR({{index}}, {{expr}});
+{%- endfor %}
+{%- endif %}
+{% if buffer %}
+    /* Perform the call. */
+    output_tag = seL4_Call(_service, tag);
+{%- else %}
+    /* Perform the call, passing in-register arguments directly. */
+    output_tag = seL4_CallWithMRs(_service, tag,
+        {% for mr in stub.mrs %}&mr{{loop.index0}}{% if not loop.last %}, {% endif %}{% endfor %});
+{%- endif %}
+{%- if stub.result_fields %}
+    result.error = seL4_MessageInfo_get_label(output_tag);
+{%- else %}
+    result = (seL4_Error) seL4_MessageInfo_get_label(output_tag);
+{%- endif %}
+{%- if not buffer %}
+
+    /* Unmarshal registers into IPC buffer on error. */
+    if ({{stub.error}} != seL4_NoError) {
+{%- for mr in stub.mrs %}
+        seL4_SetMR({{loop.index0}}, mr{{loop.index0}});
+{%- endfor %}
+#ifdef CONFIG_KERNEL_INVOCATION_REPORT_ERROR_IPC
+        if (seL4_CanPrintError()) {
+            seL4_DebugPutString(seL4_GetDebugError());
+        }
+#endif
+        return result;
+    }
+{%- endif %}
+{%- if stub.outputs %}
+
+    /* Unmarshal result. */
+{%- for output in stub.outputs %}
+    {{output}};
+{%- endfor %}
+{%- endif %}
+    return result;
+}
+{%- if stub.condition %}
+#endif
+{%- endif %}
+{% endfor %}
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct CStub {
+    name: String,
+    condition: Option<String>,
+    comment: Vec<String>,
+    return_type: String,
+    params: String,
+    label: String,
+    /// Fields of the result struct other than `error`, empty when only the error is returned.
+    result_fields: Vec<String>,
+    /// The error returned, either `result` or `result.error`.
+    error: String,
+    extra_caps: Vec<String>,
+    /// Number of message registers.
+    length: usize,
+    /// Initial values of the in-register message registers.
+    mrs: Vec<String>,
+    /// The remaining message registers set in the ipc buffer.
+    buffered: Vec<(usize, String)>,
+    /// Statements copying the message registers into outputs.
+    outputs: Vec<String>,
+}
+
+// Masks of sub-word values are given the type of a word.
+fn word_const_suffix(word_size: usize) -> &'static str {
+    if word_size == 64 {
+        "ull"
+    } else {
+        "ul"
+    }
+}
+
+fn word_type(word_size: usize) -> &'static str {
+    if word_size == 64 {
+        "seL4_Uint64"
+    } else {
+        "seL4_Uint32"
+    }
+}
+
+fn parameter_declaration(param: &StubParam, word_size: usize) -> String {
+    if param.typ.pass_by_reference(word_size) {
+        format!("{} *{}", param.typ.name, param.name)
+    } else {
+        format!("{} {}", param.typ.name, param.name)
+    }
+}
+
+fn marshal_expression(param: &StubParam, piece: &Piece, word_size: usize) -> String {
+    let name = &param.name;
+    match piece.slot {
+        Slot::Whole => name.clone(),
+        Slot::Bits { offset, bits } => {
+            let expr = format!(
+                "({} & {:#x}{})",
+                name,
+                mask(bits),
+                word_const_suffix(word_size)
+            );
+            if offset > 0 {
+                format!("({} << {})", expr, offset)
+            } else {
+                expr
+            }
+        }
+        Slot::DoubleWord { high: false } => {
+            format!("({}) {}", word_type(word_size), name)
+        }
+        Slot::DoubleWord { high: true } => {
+            format!("({}) ({} >> {})", word_type(word_size), name, word_size)
+        }
+        Slot::Word(i) if param.typ.kind == TypeKind::Struct => {
+            format!("{}->{}", name, param.typ.members[i])
+        }
+        Slot::Word(i) => format!("{}.words[{}]", name, i),
+    }
+}
+
+fn unmarshal_statements(param: &StubParam, source: impl Fn(usize) -> String) -> Vec<String> {
+    let name = &param.name;
+    let mut statements = Vec::new();
+    for piece in &param.pieces {
+        let word = source(piece.mr);
+        statements.push(match piece.slot {
+            Slot::Whole => format!("result.{} = {}", name, word),
+            Slot::Bits { offset: 0, bits } => {
+                format!("result.{} = ({} & {:#x})", name, word, mask(bits))
+            }
+            Slot::Bits { offset, bits } => {
+                format!(
+                    "result.{} = ({} >> {}) & {:#x}",
+                    name,
+                    word,
+                    offset,
+                    mask(bits)
+                )
+            }
+            Slot::DoubleWord { high: false } => format!(
+                "result.{} = ((seL4_Uint64){} + ((seL4_Uint64){} << 32))",
+                name,
+                word,
+                source(piece.mr + 1)
+            ),
+            Slot::DoubleWord { high: true } => continue,
+            Slot::Word(i) if param.typ.kind == TypeKind::Struct => {
+                format!("{}->{} = {}", name, param.typ.members[i], word)
+            }
+            Slot::Word(i) => format!("result.{}.words[{}] = {}", name, i, word),
+        });
+    }
+    statements
+}
+
+// Documentation is passed through as the xml it was written in,
+// the manual tooling knows what to make of the elements.
+struct Doxygen;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl DocFormat for Doxygen {
+    fn leaf(&self, leaf: &LeafNode) -> String {
+        match leaf {
+            LeafNode::PCData(text) => escape(text),
+            LeafNode::TextTT(text) => format!("<texttt text=\"{}\"/>", escape(text)),
+            LeafNode::AutoRef { label } => format!("<autoref label=\"{}\"/>", escape(label)),
+            LeafNode::ShortRef { sec } => format!("<shortref sec=\"{}\"/>", escape(sec)),
+            LeafNode::Obj { name } => format!("<obj name=\"{}\"/>", escape(name)),
+        }
+    }
+
+    fn docref(&self, text: String) -> String {
+        format!("<docref>{}</docref>", text)
+    }
+
+    fn error_enum_desc(&self) -> String {
+        "<errorenumdesc/>".to_string()
+    }
+}
+
+fn comment(stub: &Stub) -> Vec<String> {
+    let mut lines = vec![format!(
+        "@xmlonly <manual name=\"{}\" label=\"{}\"/> @endxmlonly",
+        stub.manual_name, stub.manual_label
+    )];
+    let brief = Doxygen.line(&stub.brief);
+    if !brief.is_empty() {
+        lines.push(format!("@brief @xmlonly {} @endxmlonly", brief));
+    }
+    let description = Doxygen.lines(&stub.description);
+    if !description.is_empty() {
+        lines.push(String::new());
+        lines.push("@xmlonly".to_string());
+        lines.extend(description);
+        lines.push("@endxmlonly".to_string());
+    }
+    lines.push(String::new());
+    for param in std::iter::once(&stub.service).chain(&stub.params) {
+        let dir = match param.dir {
+            Dir::In => "in",
+            Dir::Out => "out",
+        };
+        let line = format!(
+            "@param[{}] {} {}",
+            dir,
+            param.name,
+            Doxygen.line(&param.description)
+        );
+        lines.push(line.trim_end().to_string());
+    }
+    let return_description = Doxygen.return_line(&stub.return_value);
+    if !return_description.is_empty() {
+        lines.push(format!(
+            "@return @xmlonly {} @endxmlonly",
+            return_description
+        ));
+    }
+    lines
+}
+
+pub fn c_stub(stub: &Stub) -> CStub {
+    let word_size = stub.word_size;
+    let message_registers = stub.message_registers;
+
+    let params = std::iter::once(parameter_declaration(&stub.service, word_size))
+        .chain(
+            stub.inputs()
+                .map(|param| parameter_declaration(param, word_size)),
+        )
+        .chain(
+            stub.outputs()
+                .filter(|param| param.typ.pass_by_reference(word_size))
+                .map(|param| parameter_declaration(param, word_size)),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let words: Vec<String> = stub
+        .input_words()
+        .iter()
+        .map(|word| {
+            word.iter()
+                .map(|(param, piece)| marshal_expression(param, piece, word_size))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        })
+        .collect();
+    let mrs = (0..message_registers)
+        .map(|i| words.get(i).cloned().unwrap_or_else(|| "0".to_string()))
+        .collect();
+    let buffered = words
+        .iter()
+        .cloned()
+        .enumerate()
+        .skip(message_registers)
+        .collect();
+
+    let source = |word: usize| {
+        if word < message_registers {
+            format!("mr{}", word)
+        } else {
+            format!("seL4_GetMR({})", word)
+        }
+    };
+
+    let result_fields: Vec<String> = stub
+        .outputs()
+        .filter(|param| !param.typ.pass_by_reference(word_size))
+        .map(|param| parameter_declaration(param, word_size))
+        .collect();
+    let (return_type, error) = if stub.returns_struct() {
+        (format!("{}_t", stub.name()), "result.error")
+    } else {
+        ("seL4_Error".to_string(), "result")
+    };
+
+    CStub {
+        name: stub.name(),
+        condition: stub.condition.clone(),
+        comment: comment(stub),
+        return_type,
+        params,
+        label: stub.label.clone(),
+        result_fields,
+        error: error.to_string(),
+        extra_caps: stub.extra_caps().map(|param| param.name.clone()).collect(),
+        length: words.len(),
+        mrs,
+        buffered,
+        outputs: stub
+            .outputs()
+            .flat_map(|param| unmarshal_statements(param, source))
+            .collect(),
+    }
+}
+
+/// `(type, bytes)` for each `assert_size_correct` line.
+pub fn type_sizes(types: &TypeTable) -> Vec<(&str, usize)> {
+    types
+        .iter()
+        .map(|typ| (typ.name.as_str(), typ.native_size_bits / 8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stubs::stub::tests::{stubs_of, XML};
+    use crate::test_support::*;
+    use minijinja as jinja;
+    use sel4_xml_types::arch::Sel4Arch;
+
+    #[allow(clippy::result_large_err)]
+    fn render(sel4_arch: Sel4Arch, mcs: bool) -> Result<String, TestError> {
+        let stubs = stubs_of(XML, sel4_arch, mcs)?;
+        let mut env = jinja::Environment::new();
+        env.add_template("C_stubs", STUBS_TEMPLATE)?;
+        let ctx = jinja::context!(
+            buffer => false,
+            type_sizes => Vec::<(&str, usize)>::new(),
+            stubs => stubs.iter().map(c_stub).collect::<Vec<_>>(),
+        );
+        Ok(env.get_template("C_stubs")?.render(ctx)?)
+    }
+
+    // The definition of the stub `name` in `header`.
+    fn stub<'a>(header: &'a str, name: &str) -> &'a str {
+        let start = header
+            .find(&format!("\n{}(", name))
+            .expect("the stub is generated");
+        let end = header[start..]
+            .find("\n}\n")
+            .map_or(header.len(), |end| start + end);
+        &header[start..end]
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn packing() -> Result<(), TestError> {
+        // The bool, u8 and u16 share the first word, each aligned to its size.
+        let header = render(Sel4Arch::X86_64, false)?;
+        let set_flags = stub(&header, "seL4_TCB_SetFlags");
+        assert!(set_flags.contains("seL4_MessageInfo_new(TCBSetFlags, 0, 0, 2);"));
+        assert!(set_flags.contains(
+            "mr0 = (enable & 0x1ull) | ((level & 0xffull) << 8) | ((mask & 0xffffull) << 16);"
+        ));
+        assert!(set_flags.contains("mr1 = badge;"));
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn double_words() -> Result<(), TestError> {
+        let header = render(Sel4Arch::Ia32, false)?;
+        let set_timeout = stub(&header, "seL4_TCB_SetTimeout");
+        assert!(set_timeout.contains(
+            "    mr0 = (seL4_Uint32) timeout;\n    mr1 = (seL4_Uint32) (timeout >> 32);"
+        ));
+        assert!(set_timeout.contains(
+            "    result.remaining = ((seL4_Uint64)mr0 + ((seL4_Uint64)mr1 << 32));\n    \
+             result.count = seL4_GetMR(2);"
+        ));
+
+        // A single word on 64 bit architectures.
+        let header = render(Sel4Arch::X86_64, false)?;
+        let set_timeout = stub(&header, "seL4_TCB_SetTimeout");
+        assert!(set_timeout.contains("seL4_MessageInfo_new(TCBSetTimeout, 0, 2, 1);"));
+        assert!(set_timeout.contains("    mr0 = timeout;\n    mr1 = 0;"));
+        assert!(set_timeout.contains("    result.remaining = mr0;\n    result.count = mr1;"));
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn extra_caps() -> Result<(), TestError> {
+        let header = render(Sel4Arch::Ia32, false)?;
+        let set_timeout = stub(&header, "seL4_TCB_SetTimeout");
+        assert!(set_timeout.contains(
+            "seL4_TCB_SetTimeout(seL4_TCB _service, seL4_Time timeout, \
+             seL4_CNode cspace_root, seL4_CPtr vspace_root)"
+        ));
+        assert!(set_timeout.contains("seL4_MessageInfo_new(TCBSetTimeout, 0, 2, 2);"));
+        assert!(set_timeout
+            .contains("    seL4_SetCap(0, cspace_root);\n    seL4_SetCap(1, vspace_root);"));
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn call_with_mrs() -> Result<(), TestError> {
+        let header = render(Sel4Arch::Ia32, false)?;
+        let set_flags = stub(&header, "seL4_TCB_SetFlags");
+        assert!(set_flags.contains("seL4_CallWithMRs(_service, tag,\n        &mr0, &mr1);"));
+        assert!(set_flags.contains("        seL4_SetMR(0, mr0);\n        seL4_SetMR(1, mr1);"));
+
+        // The MCS kernel passes a single message register on ia32.
+        let header = render(Sel4Arch::Ia32, true)?;
+        let set_flags = stub(&header, "seL4_TCB_SetFlags");
+        assert!(set_flags.contains("    seL4_SetMR(1, badge);"));
+        assert!(set_flags.contains("seL4_CallWithMRs(_service, tag,\n        &mr0);"));
+        assert!(!set_flags.contains("mr1"));
+
+        let header = render(Sel4Arch::X86_64, false)?;
+        let read_pair = stub(&header, "seL4_TCB_ReadPair");
+        assert!(read_pair.contains("    mr0 = index;\n    mr1 = 0;\n    mr2 = 0;\n    mr3 = 0;"));
+        assert!(read_pair.contains("&mr0, &mr1, &mr2, &mr3);"));
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn out_struct() -> Result<(), TestError> {
+        // Structs wider than a word are written through a pointer.
+        let header = render(Sel4Arch::Ia32, false)?;
+        assert!(!header.contains("struct seL4_TCB_ReadPair {"));
+        let read_pair = stub(&header, "seL4_TCB_ReadPair");
+        assert!(read_pair.starts_with(
+            "\nseL4_TCB_ReadPair(seL4_TCB _service, seL4_Word index, seL4_Pair *pair)"
+        ));
+        assert!(read_pair.contains("    seL4_Error result;"));
+        assert!(read_pair.contains("    pair->first = mr0;\n    pair->second = mr1;"));
+
+        let header = render(Sel4Arch::Ia32, true)?;
+        let read_pair = stub(&header, "seL4_TCB_ReadPair");
+        assert!(read_pair.contains("    pair->first = mr0;\n    pair->second = seL4_GetMR(1);"));
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+// Rust counterpart of the stubs in lang_c.rs
+//
+// The message registers are laid out exactly as the C stubs lay them out,
+// only the expressions differ. Conditions are rendered into cfg predicates
+// before reaching the template.
+
+use super::stub::{mask, DocFormat, Stub, StubParam};
+use sel4_xml_types::invocations::LeafNode;
+use sel4_xml_types::layout::{Piece, Slot};
+use sel4_xml_types::types::TypeKind;
+use serde::Serialize;
+
+pub const STUBS_TEMPLATE: &str = r#"//
+// Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//
+// SPDX-License-Identifier: BSD-2-Clause
+//
+// This file was generated by gen_stubs.
+//
+// It is expected to be `include!`d into the module which includes the
+// invocation labels generated by gen_invocations, next to the libsel4
+// types and functions (`seL4_SetMR`, `seL4_CallWithMRs`, ...) it calls.
+// `seL4_Error` must implement `From<seL4_Word>` for the label of a failed invocation.
+//
+{%- for stub in stubs %}
+{%- if stub.fields %}
+{% if stub.cfg %}
+#[cfg({{stub.cfg}})]
+{%- endif %}
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+pub struct {{stub.name}}_t {
+{%- for field, typ, doc in stub.fields %}
+{%- if doc %}
+    /// {{doc}}
+{%- endif %}
+    pub {{field}}: {{typ}},
+{%- endfor %}
+}
+{%- endif %}
+{%- endfor %}
+{% for stub in stubs %}
+{%- for line in stub.doc %}
+///{% if line %} {{line}}{% endif %}
+{%- endfor %}
+{%- if stub.cfg %}
+#[cfg({{stub.cfg}})]
+{%- endif %}
+#[inline]
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub unsafe fn {{stub.name}}({{stub.params}}) -> Result<{{stub.return_type}}, seL4_Error> {
+    let tag = seL4_MessageInfo_new({{stub.label}}, 0, {{stub.extra_caps | length}}, {{stub.length}});
+{%- for mr in stub.mrs %}
+    let mut mr{{loop.index0}}: seL4_Word = {{mr}};
+{%- endfor %}
+{%- for cap in stub.extra_caps %}
+    seL4_SetCap({{loop.index0}}, {{cap}});
+{%- endfor %}
+{%- for index, expr in stub.buffered %}
+    seL4_SetMR({{index}}, {{expr}});
+{%- endfor %}
+{% if buffer %}
+    let output_tag = seL4_Call(_service, tag);
+{%- else %}
+    let output_tag = seL4_CallWithMRs(
+        _service,
+        tag,
+{%- for mr in stub.mrs %}
+        &mut mr{{loop.index0}},
+{%- endfor %}
+    );
+{%- endif %}
+    let error = seL4_MessageInfo_get_label(output_tag);
+    if error != 0 {
+{%- if not buffer %}
+        // Unmarshal registers into IPC buffer on error.
+{%- for mr in stub.mrs %}
+        seL4_SetMR({{loop.index0}}, mr{{loop.index0}});
+{%- endfor %}
+{%- endif %}
+        #[cfg({{report_error_ipc}})]
+        if seL4_CanPrintError() != 0 {
+            seL4_DebugPutString(seL4_GetDebugError());
+        }
+        return Err(seL4_Error::from(error));
+    }
+    Ok({{stub.result}})
+}
+{% endfor %}"#;
+
+#[derive(Debug, Serialize)]
+pub struct RustStub {
+    name: String,
+    cfg: Option<String>,
+    doc: Vec<String>,
+    params: String,
+    /// `(name, type, doc)` of the result struct, empty unless there are several outputs.
+    fields: Vec<(String, String, String)>,
+    return_type: String,
+    label: String,
+    extra_caps: Vec<String>,
+    length: usize,
+    mrs: Vec<String>,
+    buffered: Vec<(usize, String)>,
+    /// The value returned when the invocation succeeds.
+    result: String,
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "priv", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+    "try", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+// Params such as `type` get an underscore appended.
+fn ident(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+pub(crate) struct Markdown;
+
+impl DocFormat for Markdown {
+    fn leaf(&self, leaf: &LeafNode) -> String {
+        match leaf {
+            LeafNode::PCData(text) => text.clone(),
+            LeafNode::TextTT(text) => format!("`{}`", text),
+            LeafNode::AutoRef { label } => format!("*{}*", label),
+            LeafNode::ShortRef { sec } => format!("*{}*", sec),
+            LeafNode::Obj { name } => format!("`{}`", name),
+        }
+    }
+
+    fn docref(&self, text: String) -> String {
+        text
+    }
+
+    fn error_enum_desc(&self) -> String {
+        "An `seL4_Error` describing why the invocation failed.".to_string()
+    }
+}
+
+fn doc(stub: &Stub) -> Vec<String> {
+    let mut lines = Vec::new();
+    let brief = Markdown.line(&stub.brief);
+    if !brief.is_empty() {
+        lines.push(brief);
+    }
+    let description = Markdown.lines(&stub.description);
+    if !description.is_empty() {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(description);
+    }
+    if !lines.is_empty() {
+        lines.push(String::new());
+    }
+    for param in std::iter::once(&stub.service).chain(stub.inputs()) {
+        let line = format!(
+            "* `{}` {}",
+            ident(&param.name),
+            Markdown.line(&param.description)
+        );
+        lines.push(line.trim_end().to_string());
+    }
+    let return_description = Markdown.return_line(&stub.return_value);
+    if !return_description.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("Returns {}", return_description));
+    }
+    lines
+}
+
+fn parameter_declaration(param: &StubParam, word_size: usize) -> String {
+    if param.typ.pass_by_reference(word_size) {
+        format!("{}: &{}", ident(&param.name), param.typ.name)
+    } else {
+        format!("{}: {}", ident(&param.name), param.typ.name)
+    }
+}
+
+fn marshal_expression(param: &StubParam, piece: &Piece, word_size: usize) -> String {
+    let name = ident(&param.name);
+    match piece.slot {
+        Slot::Whole => format!("{} as seL4_Word", name),
+        Slot::Bits { offset: 0, bits } => {
+            format!("{} as seL4_Word & {:#x}", name, mask(bits))
+        }
+        Slot::Bits { offset, bits } => {
+            format!("({} as seL4_Word & {:#x}) << {}", name, mask(bits), offset)
+        }
+        Slot::DoubleWord { high: false } => format!("{} as seL4_Word", name),
+        Slot::DoubleWord { high: true } => {
+            format!("({} >> {}) as seL4_Word", name, word_size)
+        }
+        Slot::Word(i) if param.typ.kind == TypeKind::Struct => {
+            format!("{}.{}", name, param.typ.members[i])
+        }
+        Slot::Word(i) => format!("{}.words[{}]", name, i),
+    }
+}
+
+// The value of an output param, read back from the message registers.
+fn unmarshal_expression(param: &StubParam, source: impl Fn(usize) -> String) -> String {
+    let typ = &param.typ.name;
+    let mut members = Vec::new();
+    let mut words = Vec::new();
+    for piece in &param.pieces {
+        let word = source(piece.mr);
+        match piece.slot {
+            Slot::Whole => return format!("{} as {}", word, typ),
+            Slot::Bits { offset: 0, bits } => {
+                return format!("({} & {:#x}) as {}", word, mask(bits), typ)
+            }
+            Slot::Bits { offset, bits } => {
+                return format!("(({} >> {}) & {:#x}) as {}", word, offset, mask(bits), typ)
+            }
+            Slot::DoubleWord { high: false } => {
+                return format!(
+                    "({} as seL4_Uint64 | ({} as seL4_Uint64) << 32) as {}",
+                    word,
+                    source(piece.mr + 1),
+                    typ
+                )
+            }
+            Slot::DoubleWord { high: true } => {}
+            Slot::Word(i) if param.typ.kind == TypeKind::Struct => {
+                members.push(format!("{}: {}", param.typ.members[i], word))
+            }
+            Slot::Word(_) => words.push(word),
+        }
+    }
+    if members.is_empty() {
+        format!("{} {{ words: [{}] }}", typ, words.join(", "))
+    } else {
+        format!("{} {{ {} }}", typ, members.join(", "))
+    }
+}
+
+pub fn rust_stub(stub: &Stub, cfg: Option<String>) -> RustStub {
+    let word_size = stub.word_size;
+    let message_registers = stub.message_registers;
+
+    let params = std::iter::once(parameter_declaration(&stub.service, word_size))
+        .chain(
+            stub.inputs()
+                .map(|param| parameter_declaration(param, word_size)),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let words: Vec<String> = stub
+        .input_words()
+        .iter()
+        .map(|word| match word.as_slice() {
+            [(param, piece)] => marshal_expression(param, piece, word_size),
+            pieces => pieces
+                .iter()
+                .map(|(param, piece)| format!("({})", marshal_expression(param, piece, word_size)))
+                .collect::<Vec<_>>()
+                .join(" | "),
+        })
+        .collect();
+    let mrs = (0..message_registers)
+        .map(|i| words.get(i).cloned().unwrap_or_else(|| "0".to_string()))
+        .collect();
+    let buffered = words
+        .iter()
+        .cloned()
+        .enumerate()
+        .skip(message_registers)
+        .collect();
+
+    let source = |word: usize| {
+        if word < message_registers {
+            format!("mr{}", word)
+        } else {
+            format!("seL4_GetMR({})", word)
+        }
+    };
+
+    // Every output is returned by value, a single one as is and several in a struct.
+    let outputs: Vec<&StubParam> = stub.outputs().collect();
+    let mut fields = Vec::new();
+    let (return_type, result) = match outputs.as_slice() {
+        [] => ("()".to_string(), "()".to_string()),
+        [output] => (
+            output.typ.name.to_string(),
+            unmarshal_expression(output, source),
+        ),
+        outputs => {
+            let mut values = Vec::new();
+            for output in outputs {
+                let field = ident(&output.name);
+                values.push(format!(
+                    "{}: {}",
+                    field,
+                    unmarshal_expression(output, source)
+                ));
+                fields.push((
+                    field,
+                    output.typ.name.to_string(),
+                    Markdown.line(&output.description),
+                ));
+            }
+            let name = format!("{}_t", stub.name());
+            let result = format!("{} {{ {} }}", name, values.join(", "));
+            (name, result)
+        }
+    };
+
+    RustStub {
+        name: stub.name(),
+        cfg,
+        doc: doc(stub),
+        params,
+        fields,
+        return_type,
+        label: format!("{}::{} as seL4_Word", stub.layer.enum_name(), stub.label),
+        extra_caps: stub.extra_caps().map(|param| ident(&param.name)).collect(),
+        length: words.len(),
+        mrs,
+        buffered,
+        result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stubs::stub::tests::{stubs_of, XML};
+    use crate::test_support::*;
+    use minijinja as jinja;
+    use sel4_xml_types::arch::Sel4Arch;
+
+    #[allow(clippy::result_large_err)]
+    fn render(sel4_arch: Sel4Arch, mcs: bool) -> Result<String, TestError> {
+        let stubs = stubs_of(XML, sel4_arch, mcs)?;
+        let mut env = jinja::Environment::new();
+        env.add_template("Rust_stubs", STUBS_TEMPLATE)?;
+        let ctx = jinja::context!(
+            buffer => false,
+            report_error_ipc => r#"feature = "kernel_invocation_report_error_ipc""#,
+            stubs => stubs.iter().map(|stub| rust_stub(stub, None)).collect::<Vec<_>>(),
+        );
+        Ok(env.get_template("Rust_stubs")?.render(ctx)?)
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn results() -> Result<(), TestError> {
+        let stubs = render(Sel4Arch::Ia32, false)?;
+        // Several outputs are returned in a struct, a 64 bit one from two words.
+        assert!(stubs.contains(
+            "pub struct seL4_TCB_SetTimeout_t {
+    /// What was left of the old timeout.
+    pub remaining: seL4_Time,
+    pub count: seL4_Word,
+}"
+        ));
+        assert!(stubs.contains(
+            "pub unsafe fn seL4_TCB_SetTimeout(_service: seL4_TCB, timeout: seL4_Time, \
+             cspace_root: seL4_CNode, vspace_root: seL4_CPtr) \
+             -> Result<seL4_TCB_SetTimeout_t, seL4_Error> {"
+        ));
+        assert!(stubs.contains(
+            "    let mut mr0: seL4_Word = timeout as seL4_Word;
+    let mut mr1: seL4_Word = (timeout >> 32) as seL4_Word;
+    seL4_SetCap(0, cspace_root);
+    seL4_SetCap(1, vspace_root);"
+        ));
+        assert!(stubs.contains(
+            "    Ok(seL4_TCB_SetTimeout_t { \
+             remaining: (mr0 as seL4_Uint64 | (mr1 as seL4_Uint64) << 32) as seL4_Time, \
+             count: seL4_GetMR(2) as seL4_Word })"
+        ));
+
+        // A single one as it is, structs included.
+        assert!(stubs.contains(
+            "pub unsafe fn seL4_TCB_ReadPair(_service: seL4_TCB, index: seL4_Word) \
+             -> Result<seL4_Pair, seL4_Error> {"
+        ));
+        assert!(stubs.contains("    Ok(seL4_Pair { first: mr0, second: mr1 })"));
+
+        // The first word packs the bool, u8 and u16.
+        assert!(stubs.contains(
+            "    let mut mr0: seL4_Word = (enable as seL4_Word & 0x1) | \
+             ((level as seL4_Word & 0xff) << 8) | ((mask as seL4_Word & 0xffff) << 16);"
+        ));
+        assert!(stubs.contains("-> Result<(), seL4_Error> {"));
+
+        // The MCS kernel passes one message register on ia32, the rest go through the ipc buffer.
+        let stubs = render(Sel4Arch::Ia32, true)?;
+        assert!(stubs.contains(
+            "    Ok(seL4_TCB_SetTimeout_t { \
+             remaining: (mr0 as seL4_Uint64 | (seL4_GetMR(1) as seL4_Uint64) << 32) as seL4_Time, \
+             count: seL4_GetMR(2) as seL4_Word })"
+        ));
+        Ok(())
+    }
+}
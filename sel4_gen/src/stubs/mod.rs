@@ -0,0 +1,164 @@
+// Derived from syscall_stub_gen.py and licensed the same
+//#
+//# Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//#
+//# SPDX-License-Identifier: BSD-2-Clause
+//#
+
+use crate::{load_cfg_map, load_config, parse_api, read, write, Error, Language, Templates};
+use minijinja as jinja;
+use sel4_xml_types::arch::Sel4Arch;
+use sel4_xml_types::condition::Condition;
+use sel4_xml_types::diagnostic::Diagnostic;
+use sel4_xml_types::invocation_table::Layer;
+use sel4_xml_types::kernel_tree::{KernelTree, KernelTreeError};
+use sel4_xml_types::xref::LabelIndex;
+use std::path::PathBuf;
+use std::process;
+
+mod lang_c;
+mod lang_rust;
+mod stub;
+mod types;
+
+pub(crate) use lang_rust::Markdown;
+pub(crate) use stub::DocFormat;
+pub use stub::StubError;
+use stub::StubOptions;
+
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("C_stubs", lang_c::STUBS_TEMPLATE),
+    ("Rust_stubs", lang_rust::STUBS_TEMPLATE),
+];
+
+/// The stubs of a sel4_arch, from `xml` and optionally `sel4_arch_xml` and
+/// `arch_xml`, or from the interfaces of `sel4_arch` in `kernel_tree`.
+///
+/// Written to `dest`, or where the seL4 build writes them under `out_dir`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub lang: Language,
+    pub sel4_arch: Sel4Arch,
+    pub mcs: bool,
+    pub buffer: bool,
+    pub xml: Option<PathBuf>,
+    pub sel4_arch_xml: Option<PathBuf>,
+    pub arch_xml: Option<PathBuf>,
+    pub kernel_tree: Option<PathBuf>,
+    pub dest: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub cfg_map: Option<PathBuf>,
+    pub validate: bool,
+    pub labels: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub template_dir: Option<PathBuf>,
+}
+
+#[allow(clippy::result_large_err)]
+pub fn run(options: &Options) -> Result<(), Error> {
+    let templates = Templates::load(TEMPLATES, options.template_dir.as_deref())?;
+    let env = templates.environment()?;
+
+    let layers = match (&options.xml, &options.kernel_tree) {
+        (Some(xml), None) => [
+            (Layer::Generic, Some(xml.clone())),
+            (Layer::Sel4Arch, options.sel4_arch_xml.clone()),
+            (Layer::Arch, options.arch_xml.clone()),
+        ],
+        (None, Some(kernel_tree)) => {
+            let files = KernelTree::discover(kernel_tree)?
+                .interfaces(options.sel4_arch)
+                .ok_or(KernelTreeError::MissingSel4Arch(options.sel4_arch))?;
+            [
+                (Layer::Generic, Some(files.sel4_xml)),
+                (Layer::Sel4Arch, Some(files.sel4_arch_xml)),
+                (Layer::Arch, Some(files.arch_xml)),
+            ]
+        }
+        _ => return Err(Error::Usage("either --xml or --kernel-tree is required")),
+    };
+    let dest = match (&options.dest, &options.out_dir) {
+        (Some(dest), None) => dest.clone(),
+        (None, Some(out_dir)) => {
+            let mut dest = out_dir.join("libsel4/include/interfaces/sel4_client.h");
+            if let Language::Rust = options.lang {
+                dest.set_extension("rs");
+            }
+            dest
+        }
+        _ => return Err(Error::Usage("either --dest or --out-dir is required")),
+    };
+
+    let labels = match &options.labels {
+        Some(path) => Some(LabelIndex::parse(&read(path)?)),
+        None => None,
+    };
+    let config = load_config(options.config.as_deref())?;
+    let mut apis = Vec::new();
+    for (layer, xml) in layers {
+        if let Some(xml) = xml {
+            let (api, s) = parse_api(&xml, options.validate)?;
+            if let Some(labels) = &labels {
+                let dangling = labels.resolve(&api);
+                for err in &dangling {
+                    Diagnostic::from_dangling_ref(err, &s, labels).emit(&xml, &s)?;
+                }
+                if !dangling.is_empty() {
+                    process::exit(1);
+                }
+            }
+            let api = match &config {
+                Some(config) => api.configure(config, 0)?.api,
+                None => api,
+            };
+            apis.push((layer, api));
+        }
+    }
+
+    let mut types = types::type_table(options.sel4_arch);
+    for (_, api) in &apis {
+        types.declare_structs(api)?;
+    }
+    let stub_options = StubOptions {
+        message_registers: if options.buffer {
+            0
+        } else {
+            options.sel4_arch.message_registers(options.mcs)
+        },
+        types,
+    };
+    let stubs = stub::stubs(&apis, &stub_options)?;
+
+    let output = match options.lang {
+        Language::C => {
+            let ctx = jinja::context!(
+                buffer => options.buffer,
+                type_sizes => lang_c::type_sizes(&stub_options.types),
+                stubs => stubs.iter().map(lang_c::c_stub).collect::<Vec<_>>(),
+            );
+            env.get_template("C_stubs")?.render(ctx)?
+        }
+        Language::Rust => {
+            let cfg_map = load_cfg_map(options.cfg_map.as_deref())?;
+            let mut rust_stubs = Vec::new();
+            for stub in &stubs {
+                let cfg = match &stub.condition {
+                    Some(condition) => Some(cfg_map.render(&condition.parse::<Condition>()?)?),
+                    None => None,
+                };
+                rust_stubs.push(lang_rust::rust_stub(stub, cfg));
+            }
+            let report_error_ipc = cfg_map.render(&Condition::Defined(
+                "CONFIG_KERNEL_INVOCATION_REPORT_ERROR_IPC".to_string(),
+            ))?;
+            let ctx = jinja::context!(
+                buffer => options.buffer,
+                report_error_ipc => report_error_ipc,
+                stubs => rust_stubs,
+            );
+            env.get_template("Rust_stubs")?.render(ctx)?
+        }
+    };
+    write(&dest, &output)?;
+    Ok(())
+}
@@ -0,0 +1,369 @@
+// The language independent part of syscall_stub_gen.py
+//
+// Each method of an interface becomes a `Stub`, which records where every
+// parameter lives in the message. The language modules only have to turn
+// that into expressions.
+
+use sel4_xml_types::invocation_table::Layer;
+use sel4_xml_types::invocations::{Api, ApiElement, DocLeaf, LeafNode, Method, Position, Return};
+use sel4_xml_types::layout::{LayoutError, MethodLayout, Piece};
+use sel4_xml_types::types::{ParamType, TypeKind, TypeTable};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StubError {
+    #[error("{0}")]
+    Layout(#[from] LayoutError),
+    #[error("interface '{0}' is not a known capability type")]
+    NotACapability(String),
+    #[error("struct '{0}' needs a <struct> declaration with a member for each word")]
+    StructMembers(String),
+    #[error("method '{0}' needs {1} message registers, more than seL4_MsgMaxLength")]
+    MessageTooLong(String, usize),
+    #[error("method '{0}' sends {1} caps, more than seL4_MsgMaxExtraCaps")]
+    TooManyExtraCaps(String, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir {
+    In,
+    Out,
+}
+
+/// The mask of a `bits` wide value.
+pub fn mask(bits: usize) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StubParam {
+    pub name: String,
+    pub typ: ParamType,
+    pub dir: Dir,
+    pub description: Vec<DocLeaf>,
+    /// Where the param lives in the message, empty for caps.
+    pub pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stub {
+    pub interface: String,
+    pub method: String,
+    /// The invocation label e.g. `TCBConfigure`
+    pub label: String,
+    /// The enum the label belongs to.
+    pub layer: Layer,
+    pub condition: Option<String>,
+    pub word_size: usize,
+    /// Message registers passed in cpu registers, zero when only the ipc buffer is used.
+    pub message_registers: usize,
+    pub manual_name: String,
+    pub manual_label: String,
+    pub brief: Vec<DocLeaf>,
+    pub description: Vec<DocLeaf>,
+    pub return_value: Vec<Return>,
+    /// The invoked capability, `_service`
+    pub service: StubParam,
+    /// Every other param in declaration order.
+    pub params: Vec<StubParam>,
+    pub layout: MethodLayout,
+}
+
+impl Stub {
+    /// The function name e.g. `seL4_TCB_Configure`
+    pub fn name(&self) -> String {
+        format!("{}_{}", self.interface, self.method)
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = &StubParam> {
+        self.params.iter().filter(|param| param.dir == Dir::In)
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &StubParam> {
+        self.params.iter().filter(|param| param.dir == Dir::Out)
+    }
+
+    /// Cap params other than the service, sent with `seL4_SetCap`.
+    pub fn extra_caps(&self) -> impl Iterator<Item = &StubParam> {
+        self.inputs()
+            .filter(|param| param.typ.kind == TypeKind::Cap)
+    }
+
+    /// The pieces of the input params making up each message register.
+    pub fn input_words(&self) -> Vec<Vec<(&StubParam, Piece)>> {
+        let mut words = vec![Vec::new(); self.layout.input_length];
+        for param in self.inputs() {
+            for piece in &param.pieces {
+                words[piece.mr].push((param, *piece));
+            }
+        }
+        words
+    }
+
+    /// Whether any output is returned by value, otherwise the stub just returns the error.
+    pub fn returns_struct(&self) -> bool {
+        self.outputs()
+            .any(|param| !param.typ.pass_by_reference(self.word_size))
+    }
+}
+
+/// Options shared by every stub of a generated file.
+#[derive(Debug, Clone)]
+pub struct StubOptions {
+    /// Including the `<struct>`s declared by the apis.
+    pub types: TypeTable,
+    pub message_registers: usize,
+}
+
+/// Builds a stub for every method of every interface of `apis`.
+pub fn stubs(apis: &[(Layer, Api)], options: &StubOptions) -> Result<Vec<Stub>, StubError> {
+    let mut stubs = Vec::new();
+    for (layer, api) in apis {
+        for child in &api.children {
+            if let ApiElement::Interface {
+                name,
+                manual_name,
+                cap_desc,
+                methods,
+                ..
+            } = child
+            {
+                let interface_type = options
+                    .types
+                    .get(name)
+                    .filter(|typ| typ.kind == TypeKind::Cap)
+                    .ok_or_else(|| StubError::NotACapability(name.clone()))?;
+                let manual_name = manual_name.as_deref().unwrap_or(name);
+                for method in methods {
+                    let service_description = cap_desc.clone().unwrap_or_default()
+                        + method
+                            .cap_param
+                            .as_ref()
+                            .map_or("", |cap_param| cap_param.append_description());
+                    let service_description = if service_description.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![DocLeaf::Leaf(
+                            LeafNode::PCData(service_description),
+                            Position::default(),
+                        )]
+                    };
+                    let service = StubParam {
+                        name: "_service".to_string(),
+                        typ: interface_type.clone(),
+                        dir: Dir::In,
+                        description: service_description,
+                        pieces: Vec::new(),
+                    };
+                    stubs.push(stub(name, manual_name, *layer, service, method, options)?);
+                }
+            }
+        }
+    }
+    Ok(stubs)
+}
+
+fn stub(
+    interface: &str,
+    interface_manual_name: &str,
+    layer: Layer,
+    service: StubParam,
+    method: &Method,
+    options: &StubOptions,
+) -> Result<Stub, StubError> {
+    let layout = MethodLayout::new(method, &options.types)?;
+    if layout.exceeds_msg_max_length() {
+        let length = layout.input_length.max(layout.output_length);
+        return Err(StubError::MessageTooLong(method.id.clone(), length));
+    }
+    if layout.exceeds_msg_max_extra_caps() {
+        let caps = layout.extra_caps.len();
+        return Err(StubError::TooManyExtraCaps(method.id.clone(), caps));
+    }
+
+    // The layout keeps the inputs and outputs apart, the docs want them in declaration order.
+    let mut params = Vec::new();
+    let mut inputs = layout.inputs.iter();
+    let mut outputs = layout.outputs.iter();
+    for param in &method.params {
+        let (dir, param_layout) = if param.dir() == "in" {
+            (Dir::In, inputs.next())
+        } else {
+            (Dir::Out, outputs.next())
+        };
+        let param_layout = param_layout.expect("MethodLayout has a layout for every param");
+        // The words of a struct are named after its members.
+        let typ = &param_layout.typ;
+        if typ.kind == TypeKind::Struct && typ.members.is_empty() {
+            return Err(StubError::StructMembers(typ.name.clone()));
+        }
+        params.push(StubParam {
+            name: param_layout.name.clone(),
+            typ: param_layout.typ.clone(),
+            dir,
+            description: param.description().to_vec(),
+            pieces: param_layout.pieces.clone(),
+        });
+    }
+
+    let method_manual_name = method.manual_name.as_deref().unwrap_or(&method.name);
+
+    Ok(Stub {
+        interface: interface.to_string(),
+        method: method.name.clone(),
+        label: method.id.clone(),
+        layer,
+        condition: method.condition.clone(),
+        word_size: options.types.word_size(),
+        message_registers: options.message_registers,
+        manual_name: format!("{} - {}", interface_manual_name, method_manual_name),
+        manual_label: method.manual_label.clone().unwrap_or_else(|| {
+            format!(
+                "{}_{}",
+                interface_manual_name.to_lowercase(),
+                method.name.to_lowercase()
+            )
+        }),
+        brief: method.brief.clone(),
+        description: method.description.clone(),
+        return_value: method.return_value.clone(),
+        service,
+        params,
+        layout,
+    })
+}
+
+/// How a language writes the leaves of the IDL documentation.
+pub trait DocFormat {
+    fn leaf(&self, leaf: &LeafNode) -> String;
+    fn docref(&self, text: String) -> String;
+    /// `<errorenumdesc/>` in a `<return>`
+    fn error_enum_desc(&self) -> String;
+
+    fn text(&self, leaves: &[DocLeaf]) -> String {
+        leaves
+            .iter()
+            .map(|leaf| match leaf {
+                DocLeaf::Leaf(leaf, _) => self.leaf(leaf),
+                DocLeaf::DocRef(leaves, _) => {
+                    self.docref(leaves.iter().map(|leaf| self.leaf(leaf)).collect())
+                }
+            })
+            .collect()
+    }
+
+    /// The documentation on a single line.
+    fn line(&self, leaves: &[DocLeaf]) -> String {
+        normalize_line(&self.text(leaves))
+    }
+
+    /// Keeps the paragraphs of longer descriptions, but not their indentation.
+    fn lines(&self, leaves: &[DocLeaf]) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for line in self.text(leaves).lines().map(str::trim) {
+            if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push(line.to_string());
+            }
+        }
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    }
+
+    fn return_line(&self, return_value: &[Return]) -> String {
+        let text = return_value
+            .iter()
+            .map(|ret| match ret {
+                Return::ErrorEnumDesc => self.error_enum_desc(),
+                Return::Leaves(leaf) => self.text(std::slice::from_ref(leaf)),
+            })
+            .collect::<String>();
+        normalize_line(&text)
+    }
+}
+
+fn normalize_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::stubs::types::type_table;
+    use crate::test_support::*;
+    use sel4_xml_types::arch::Sel4Arch;
+    use std::path::Path;
+
+    pub(in crate::stubs) const XML: &str = r#"<api name="ObjectApi" label_prefix="seL4_">
+    <struct name="seL4_Pair">
+        <member name="first"/>
+        <member name="second"/>
+    </struct>
+    <interface name="seL4_TCB" manual_name="TCB" cap_description="Capability to the TCB which is being operated on.">
+        <method id="TCBSetFlags" name="SetFlags">
+            <brief>Set the flags of a thread.</brief>
+            <param dir="in" name="enable" type="seL4_Bool" description="Whether the flags are set."/>
+            <param dir="in" name="level" type="seL4_Uint8"/>
+            <param dir="in" name="mask" type="seL4_Uint16"/>
+            <param dir="in" name="badge" type="seL4_Word"/>
+        </method>
+        <method id="TCBSetTimeout" name="SetTimeout">
+            <param dir="in" name="timeout" type="seL4_Time"/>
+            <param dir="in" name="cspace_root" type="seL4_CNode"/>
+            <param dir="in" name="vspace_root" type="seL4_CPtr"/>
+            <param dir="out" name="remaining" type="seL4_Time" description="What was left of the old timeout."/>
+            <param dir="out" name="count" type="seL4_Word"/>
+        </method>
+        <method id="TCBReadPair" name="ReadPair">
+            <param dir="in" name="index" type="seL4_Word"/>
+            <param dir="out" name="pair" type="seL4_Pair"/>
+        </method>
+    </interface>
+</api>"#;
+
+    // The stubs of `xml`, as gen_stubs builds them for `sel4_arch`.
+    #[allow(clippy::result_large_err)]
+    pub(in crate::stubs) fn stubs_of(
+        xml: &str,
+        sel4_arch: Sel4Arch,
+        mcs: bool,
+    ) -> Result<Vec<Stub>, TestError> {
+        let api = Api::parse(Path::new("sel4.xml"), xml)?;
+        let mut types = type_table(sel4_arch);
+        types.declare_structs(&api)?;
+        let options = StubOptions {
+            types,
+            message_registers: sel4_arch.message_registers(mcs),
+        };
+        Ok(stubs(&[(Layer::Generic, api)], &options)?)
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn service() -> Result<(), TestError> {
+        let stubs = stubs_of(XML, Sel4Arch::X86_64, false)?;
+        let names: Vec<_> = stubs.iter().map(Stub::name).collect();
+        assert_eq!(
+            names,
+            [
+                "seL4_TCB_SetFlags",
+                "seL4_TCB_SetTimeout",
+                "seL4_TCB_ReadPair"
+            ]
+        );
+        // The interface's cap_description documents `_service`.
+        assert!(matches!(
+            &stubs[0].service.description[..],
+            [DocLeaf::Leaf(LeafNode::PCData(text), _)]
+                if text == "Capability to the TCB which is being operated on."
+        ));
+        let extra_caps: Vec<_> = stubs[1].extra_caps().map(|cap| cap.name.as_str()).collect();
+        assert_eq!(extra_caps, ["cspace_root", "vspace_root"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+// The type table of syscall_stub_gen.py
+//
+// The stub generator has to know how many bits each parameter type occupies
+// to pack it into message registers, the sizes are checked against the C
+// compiler's by the `assert_size_correct` lines of the generated header.
+
+use sel4_xml_types::arch::Sel4Arch;
+use sel4_xml_types::types::{ParamType, TypeTable};
+
+/// Every type a parameter or interface may have, other than the
+/// `<struct>`s declared by the IDL.
+pub fn type_table(sel4_arch: Sel4Arch) -> TypeTable {
+    let word_size = sel4_arch.word_size();
+    let word = ParamType::word;
+    let cap = |name: &str| ParamType::cap(name, word_size);
+    let mut types = TypeTable::new(word_size);
+
+    let user_context = |words| ParamType::structure("seL4_UserContext", word_size * words);
+    let arm = || {
+        vec![
+            word("seL4_ARM_VMAttributes", word_size),
+            word("seL4_VCPUReg", word_size),
+            cap("seL4_ARM_Page"),
+            cap("seL4_ARM_PageTable"),
+            cap("seL4_ARM_PageDirectory"),
+            cap("seL4_ARM_ASIDControl"),
+            cap("seL4_ARM_ASIDPool"),
+            cap("seL4_ARM_VCPU"),
+            cap("seL4_ARM_IOSpace"),
+            cap("seL4_ARM_IOPageTable"),
+            cap("seL4_ARM_SIDControl"),
+            cap("seL4_ARM_SID"),
+            cap("seL4_ARM_CBControl"),
+            cap("seL4_ARM_CB"),
+        ]
+    };
+    let x86 = || {
+        vec![
+            word("seL4_X86_VMAttributes", word_size),
+            cap("seL4_X86_IOPort"),
+            cap("seL4_X86_IOPortControl"),
+            cap("seL4_X86_ASIDControl"),
+            cap("seL4_X86_ASIDPool"),
+            cap("seL4_X86_IOSpace"),
+            cap("seL4_X86_Page"),
+            cap("seL4_X86_PageDirectory"),
+            cap("seL4_X86_PageTable"),
+            cap("seL4_X86_IOPageTable"),
+            cap("seL4_X86_VCPU"),
+            cap("seL4_X86_EPTPML4"),
+            cap("seL4_X86_EPTPDPT"),
+            cap("seL4_X86_EPTPD"),
+            cap("seL4_X86_EPTPT"),
+            ParamType::structure("seL4_VCPUContext", word_size * 7),
+        ]
+    };
+    let riscv = || {
+        vec![
+            word("seL4_RISCV_VMAttributes", word_size),
+            cap("seL4_RISCV_Page"),
+            cap("seL4_RISCV_PageTable"),
+            cap("seL4_RISCV_ASIDControl"),
+            cap("seL4_RISCV_ASIDPool"),
+            user_context(32),
+        ]
+    };
+
+    types.extend(match sel4_arch {
+        Sel4Arch::Aarch32 | Sel4Arch::ArmHyp => {
+            let mut types = arm();
+            types.push(user_context(19));
+            types
+        }
+        Sel4Arch::Aarch64 => {
+            let mut types = arm();
+            types.extend([
+                cap("seL4_ARM_PageUpperDirectory"),
+                cap("seL4_ARM_PageGlobalDirectory"),
+                cap("seL4_ARM_VSpace"),
+                cap("seL4_ARM_SMC"),
+                ParamType::structure("seL4_ARM_SMCContext", word_size * 8),
+                user_context(36),
+            ]);
+            types
+        }
+        Sel4Arch::Ia32 => {
+            let mut types = x86();
+            types.push(user_context(12));
+            types
+        }
+        Sel4Arch::X86_64 => {
+            let mut types = x86();
+            types.extend([cap("seL4_X64_PML4"), cap("seL4_X86_PDPT"), user_context(20)]);
+            types
+        }
+        Sel4Arch::Riscv32 | Sel4Arch::Riscv64 => riscv(),
+    });
+    types
+}
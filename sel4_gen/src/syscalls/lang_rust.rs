@@ -0,0 +1,57 @@
+// Rust counterpart of the templates in lang_c.rs
+//
+// Conditions are rendered into cfg predicates before reaching the template.
+
+const COMMON_HEADER: &str = r#"//
+// This file was generated by gen_syscalls.
+//
+// To add a system call number, edit kernel/include/api/syscall.xml
+//"#;
+
+pub const SYSCALL_TEMPLATE: &str = const_format::concatcp!(
+    r#"//
+// Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+//
+// SPDX-License-Identifier: BSD-2-Clause
+"#,
+    COMMON_HEADER,
+    r#"
+
+// System Calls, for use as `const` operands of `global_asm!`/`asm!`
+{%- for condition, list in assembler %}
+    {%- for syscall, syscall_number in list %}
+{%- if condition | length > 0 %}
+#[cfg({{condition}})]
+{%- endif %}
+pub const SYSCALL_{{upper(syscall)}}: isize = {{syscall_number}};
+    {%- endfor %}
+{%- endfor %}
+
+pub const SYSCALL_MAX: isize = -1;
+pub const SYSCALL_MIN: isize = {{syscall_min}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(isize)]
+pub enum Syscall {
+{%- for condition, list in enum %}
+   {%- for syscall, syscall_number in list %}
+   {%- if condition | length > 0 %}
+    #[cfg({{condition}})]
+   {%- endif %}
+    {{syscall}} = {{syscall_number}},
+   {%- endfor %}
+{%- endfor %}
+}
+
+// System call names, indexed by the negated syscall number.
+#[cfg({{debug_build}})]
+pub static SYSCALL_NAMES: [&str; {{1 - syscall_min}}] = [
+    "",
+{%- for condition, list in assembler %}
+    {%- for syscall, syscall_number in list %}
+    "{{syscall}}",
+    {%- endfor %}
+{%- endfor %}
+];
+"#
+);
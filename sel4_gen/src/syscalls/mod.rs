@@ -0,0 +1,194 @@
+use crate::depfile::Depfile;
+use crate::{
+    config_file, depfile_inputs, load_cfg_map, load_config, read, write, Error, Language, Templates,
+};
+use lazy_static::lazy_static;
+use minijinja as jinja;
+use sel4_xml_types::condition::{CfgMap, Condition, ConditionError};
+use sel4_xml_types::diagnostic::Diagnostic;
+use sel4_xml_types::kernel_tree::KernelTree;
+use sel4_xml_types::syscalls::*;
+use std::path::{Path, PathBuf};
+use std::process;
+
+mod lang_c;
+mod lang_rust;
+
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("kernel_header", lang_c::KERNEL_HEADER_TEMPLATE),
+    ("libsel4_header", lang_c::LIBSEL4_HEADER_TEMPLATE),
+    ("rust_module", lang_rust::SYSCALL_TEMPLATE),
+];
+
+/// The syscall numbers of `xml`, or the syscall.xml of `kernel_tree`.
+///
+/// Each output is written if its path is given. With `out_dir` those of
+/// `lang` which aren't default to where the seL4 build writes them.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub lang: Language,
+    pub xml: Option<PathBuf>,
+    pub kernel_tree: Option<PathBuf>,
+    pub kernel_header: Option<PathBuf>,
+    pub libsel4_header: Option<PathBuf>,
+    pub rust_module: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub cfg_map: Option<PathBuf>,
+    pub mcs: bool,
+    pub config: Option<PathBuf>,
+    pub template_dir: Option<PathBuf>,
+    /// Make style dependency file of every output written.
+    pub depfile: Option<PathBuf>,
+}
+
+// (condition, [(syscall, number)]), the condition is empty for unconditional syscalls.
+type ConditionGroups = Vec<(String, Vec<(String, isize)>)>;
+
+// The templates emit one `#if` per run of syscalls sharing a condition.
+fn group_by_condition(syscalls: Vec<NumberedSyscall>) -> ConditionGroups {
+    let mut groups: ConditionGroups = Vec::new();
+    for syscall in syscalls {
+        let condition = syscall.condition.unwrap_or_default();
+        match groups.last_mut() {
+            Some((last, list)) if *last == condition => list.push((syscall.name, syscall.number)),
+            _ => groups.push((condition, vec![(syscall.name, syscall.number)])),
+        }
+    }
+    groups
+}
+
+// Rust needs the conditions as cfg predicates rather than `#if` expressions.
+fn render_conditions(
+    groups: ConditionGroups,
+    cfg_map: &CfgMap,
+) -> Result<ConditionGroups, ConditionError> {
+    groups
+        .into_iter()
+        .map(|(condition, list)| {
+            if condition.is_empty() {
+                Ok((condition, list))
+            } else {
+                Ok((cfg_map.render(&condition.parse()?)?, list))
+            }
+        })
+        .collect()
+}
+
+lazy_static! {
+    static ref RE: regex::Regex = regex::Regex::new(r"[A-Z][A-Z]?[^A-Z]*").unwrap();
+}
+
+#[allow(clippy::result_large_err)]
+fn convert_to_assembler_format(_state: &jinja::State, s: String) -> Result<String, jinja::Error> {
+    Ok(RE
+        .find_iter(&s)
+        .map(|s| s.as_str().to_uppercase())
+        .collect::<Vec<String>>()
+        .join("_"))
+}
+
+// Where the seL4 build writes the outputs of `lang`, relative to `out_dir`.
+fn default_outputs(options: &Options, out_dir: &Path) -> Options {
+    let mut options = options.clone();
+    match options.lang {
+        Language::C => {
+            options
+                .kernel_header
+                .get_or_insert_with(|| out_dir.join("generated/arch/api/syscall.h"));
+            options
+                .libsel4_header
+                .get_or_insert_with(|| out_dir.join("libsel4/include/sel4/syscall.h"));
+        }
+        Language::Rust => {
+            options
+                .rust_module
+                .get_or_insert_with(|| out_dir.join("libsel4/syscall.rs"));
+        }
+    }
+    options
+}
+
+#[allow(clippy::result_large_err)]
+pub fn run(options: &Options) -> Result<(), Error> {
+    // open files/parse xml so the user gets relevant errors first.
+    let xml = match (&options.xml, &options.kernel_tree) {
+        (Some(xml), None) => xml.clone(),
+        (None, Some(kernel_tree)) => KernelTree::discover(kernel_tree)?.syscall_xml,
+        _ => return Err(Error::Usage("either --xml or --kernel-tree is required")),
+    };
+    let source = read(&xml)?;
+    let mut syscalls = match Syscalls::parse(&xml, &source) {
+        Ok(syscalls) => syscalls,
+        Err(err) => {
+            Diagnostic::from_sel4_xml_error(&err, &source).emit(&xml, &source)?;
+            process::exit(1);
+        }
+    };
+    if let Some(config) = load_config(options.config.as_deref())? {
+        syscalls = syscalls.configure(&config)?;
+    }
+    let options = match &options.out_dir {
+        Some(out_dir) => default_outputs(options, out_dir),
+        None => options.clone(),
+    };
+    let mcs = options.mcs;
+
+    // go through all the built in templates so a broken one can't go unnoticed.
+    let templates = Templates::load(TEMPLATES, options.template_dir.as_deref())?;
+    let mut env = templates.environment()?;
+    env.add_function("upper", convert_to_assembler_format);
+    let mut depfile = Depfile::new();
+    let config_file = config_file(options.config.as_deref())?;
+    let mut add_dep = |dest: &Path, template: &str| {
+        let inputs = [xml.as_path()].into_iter().chain(templates.file(template));
+        let cfg_map_file = options
+            .cfg_map
+            .as_deref()
+            .filter(|_| template == "rust_module");
+        depfile.add(
+            dest,
+            inputs.chain(depfile_inputs(cfg_map_file, config_file.as_deref())),
+        );
+    };
+
+    if let Some(kernel_header) = &options.kernel_header {
+        let template = env.get_template("kernel_header")?;
+        let ctx = jinja::context!(
+            assembler => group_by_condition(syscalls.numbered(mcs, false)),
+            enum => group_by_condition(syscalls.numbered(mcs, true)),
+            syscall_min => syscalls.syscall_min(mcs),
+
+        );
+        write(kernel_header, &template.render(ctx)?)?;
+        add_dep(kernel_header, "kernel_header");
+    }
+
+    if let Some(libsel4_header) = &options.libsel4_header {
+        let template = env.get_template("libsel4_header")?;
+        let ctx = jinja::context!(
+            enum => group_by_condition(syscalls.numbered(mcs, true)),
+
+        );
+        write(libsel4_header, &template.render(ctx)?)?;
+        add_dep(libsel4_header, "libsel4_header");
+    }
+
+    if let Some(rust_module) = &options.rust_module {
+        let cfg_map = load_cfg_map(options.cfg_map.as_deref())?;
+        let template = env.get_template("rust_module")?;
+        let debug_build = Condition::Defined("CONFIG_DEBUG_BUILD".to_string());
+        let ctx = jinja::context!(
+            assembler => render_conditions(group_by_condition(syscalls.numbered(mcs, false)), &cfg_map)?,
+            enum => render_conditions(group_by_condition(syscalls.numbered(mcs, true)), &cfg_map)?,
+            syscall_min => syscalls.syscall_min(mcs),
+            debug_build => cfg_map.render(&debug_build)?,
+        );
+        write(rust_module, &template.render(ctx)?)?;
+        add_dep(rust_module, "rust_module");
+    }
+
+    if let Some(path) = &options.depfile {
+        depfile.write(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,15 @@
+#![cfg(test)]
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TestError {
+    #[error("parsing the xml")]
+    Sel4Xml(#[from] sel4_xml_types::invocations::Sel4XmlError),
+    #[error("param types")]
+    Type(#[from] sel4_xml_types::types::TypeError),
+    #[error("building the stubs")]
+    Stub(#[from] crate::stubs::StubError),
+    #[error("rendering a template")]
+    Minijinja(#[from] minijinja::Error),
+}
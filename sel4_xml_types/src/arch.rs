@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+//
+// The architectures of the kernel, which the invocations are layered by:
+//
+//   sel4.xml                         every architecture
+//   arch_include/<arch>              x86, arm, riscv
+//   sel4_arch_include/<sel4_arch>    ia32, x86_64, aarch32, aarch64, riscv32, riscv64
+//
+// arm_hyp is aarch32 with the hypervisor extensions, libsel4 has no
+// directory of its own for it but uses those of aarch32.
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    Arm,
+    X86,
+    Riscv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sel4Arch {
+    Aarch32,
+    ArmHyp,
+    Aarch64,
+    Ia32,
+    X86_64,
+    Riscv32,
+    Riscv64,
+}
+
+impl Arch {
+    pub const ALL: &'static [Arch] = &[Arch::Arm, Arch::X86, Arch::Riscv];
+
+    /// The name of the architecture's directories e.g. `arch_include/x86`
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::Arm => "arm",
+            Arch::X86 => "x86",
+            Arch::Riscv => "riscv",
+        }
+    }
+
+    pub fn sel4_arches(self) -> impl Iterator<Item = Sel4Arch> {
+        Sel4Arch::ALL
+            .iter()
+            .copied()
+            .filter(move |sel4_arch| sel4_arch.arch() == self)
+    }
+}
+
+impl Sel4Arch {
+    pub const ALL: &'static [Sel4Arch] = &[
+        Sel4Arch::Aarch32,
+        Sel4Arch::ArmHyp,
+        Sel4Arch::Aarch64,
+        Sel4Arch::Ia32,
+        Sel4Arch::X86_64,
+        Sel4Arch::Riscv32,
+        Sel4Arch::Riscv64,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sel4Arch::Aarch32 => "aarch32",
+            Sel4Arch::ArmHyp => "arm_hyp",
+            Sel4Arch::Aarch64 => "aarch64",
+            Sel4Arch::Ia32 => "ia32",
+            Sel4Arch::X86_64 => "x86_64",
+            Sel4Arch::Riscv32 => "riscv32",
+            Sel4Arch::Riscv64 => "riscv64",
+        }
+    }
+
+    pub fn arch(self) -> Arch {
+        match self {
+            Sel4Arch::Aarch32 | Sel4Arch::ArmHyp | Sel4Arch::Aarch64 => Arch::Arm,
+            Sel4Arch::Ia32 | Sel4Arch::X86_64 => Arch::X86,
+            Sel4Arch::Riscv32 | Sel4Arch::Riscv64 => Arch::Riscv,
+        }
+    }
+
+    pub fn word_size(self) -> usize {
+        match self {
+            Sel4Arch::Aarch32 | Sel4Arch::ArmHyp | Sel4Arch::Ia32 | Sel4Arch::Riscv32 => 32,
+            Sel4Arch::Aarch64 | Sel4Arch::X86_64 | Sel4Arch::Riscv64 => 64,
+        }
+    }
+
+    /// Number of message registers passed in cpu registers by `seL4_CallWithMRs`.
+    pub fn message_registers(self, mcs: bool) -> usize {
+        match self {
+            // The MCS kernel needs another register for the reply cap.
+            Sel4Arch::Ia32 if mcs => 1,
+            Sel4Arch::Ia32 => 2,
+            _ => 4,
+        }
+    }
+
+    /// The sel4_arch whose directory of libsel4 this one uses
+    /// e.g. `sel4_arch_include/aarch32` for arm_hyp.
+    pub fn libsel4_sel4_arch(self) -> Sel4Arch {
+        match self {
+            Sel4Arch::ArmHyp => Sel4Arch::Aarch32,
+            sel4_arch => sel4_arch,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Sel4Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Arch {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Arch, String> {
+        Arch::ALL
+            .iter()
+            .copied()
+            .find(|arch| arch.name() == s)
+            .ok_or_else(|| format!("Unrecognized arch '{}'", s))
+    }
+}
+
+impl FromStr for Sel4Arch {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Sel4Arch, String> {
+        Sel4Arch::ALL
+            .iter()
+            .copied()
+            .find(|sel4_arch| sel4_arch.name() == s)
+            .ok_or_else(|| format!("Unrecognized sel4_arch '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hierarchy() {
+        let arm: Vec<_> = Arch::Arm.sel4_arches().map(Sel4Arch::name).collect();
+        assert_eq!(arm, ["aarch32", "arm_hyp", "aarch64"]);
+        for sel4_arch in Sel4Arch::ALL {
+            assert_eq!(sel4_arch.name().parse(), Ok(*sel4_arch));
+            assert!(sel4_arch.arch().sel4_arches().any(|s| s == *sel4_arch));
+        }
+        assert_eq!("x86".parse(), Ok(Arch::X86));
+        assert_eq!(Sel4Arch::X86_64.word_size(), 64);
+        assert_eq!(Sel4Arch::ArmHyp.libsel4_sel4_arch(), Sel4Arch::Aarch32);
+        assert!("arm64".parse::<Sel4Arch>().is_err());
+    }
+}
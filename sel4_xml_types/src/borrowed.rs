@@ -0,0 +1,655 @@
+use crate::invocation_helper::*;
+use crate::invocations::{self as owned, Formatting, Position, Sel4XmlError};
+use serde::Serialize;
+
+//
+// The invocation IDL model borrowing its strings from the roxmltree
+// `Document`, rather than copying every attribute and text node.
+//
+// This is what the parser produces, the owned model in invocations.rs is
+// made from it with `into_owned`. Each element keeps its roxmltree node,
+// so the `Formatting` the owned model needs for writing the xml back is
+// only worked out by `into_owned`.
+//
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Api<'a> {
+    pub position: Position,
+    pub name: Option<&'a str>,
+    pub label_prefix: Option<&'a str>,
+    pub children: Vec<ApiElement<'a>>,
+    #[serde(skip)]
+    pub node: roxmltree::Node<'a, 'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum ApiElement<'a> {
+    StructElem {
+        name: &'a str,
+        members: Vec<&'a str>,
+        #[serde(skip)]
+        node: roxmltree::Node<'a, 'a>,
+    },
+    Interface {
+        name: &'a str,
+        manual_name: Option<&'a str>,
+        cap_desc: Option<&'a str>,
+        methods: Vec<Method<'a>>,
+        #[serde(skip)]
+        node: roxmltree::Node<'a, 'a>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Method<'a> {
+    pub position: Position,
+    pub name: &'a str,
+    pub id: &'a str,
+    pub condition: Option<&'a str>,
+    pub manual_name: Option<&'a str>,
+    pub manual_label: Option<&'a str>,
+    pub brief: Vec<DocLeaf<'a>>,
+    pub description: Vec<DocLeaf<'a>>,
+    pub return_value: Vec<Return<'a>>,
+    pub cap_param: Option<CapParam<'a>>,
+    pub params: Vec<Param<'a>>,
+    pub errors: Vec<ErrorElement<'a>>,
+    #[serde(skip)]
+    pub node: roxmltree::Node<'a, 'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum Return<'a> {
+    ErrorEnumDesc,
+    Leaves(DocLeaf<'a>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Param<'a> {
+    pub position: Position,
+    pub typ: &'a str,
+    pub name: &'a str,
+    pub dir: &'a str,
+    /// The `description` attribute first, if any, then the `<description>`.
+    pub description: Vec<DocLeaf<'a>>,
+    pub description_attribute: bool,
+    pub errors: Vec<ErrorElement<'a>>,
+    #[serde(skip)]
+    pub node: roxmltree::Node<'a, 'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorElement<'a> {
+    pub position: Position,
+    pub name: &'a str,
+    /// The `description` attribute first, if any, then the `<description>`.
+    pub description: Vec<DocLeaf<'a>>,
+    pub description_attribute: bool,
+    #[serde(skip)]
+    pub node: roxmltree::Node<'a, 'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapParam<'a> {
+    pub append_description: &'a str,
+    #[serde(skip)]
+    pub node: roxmltree::Node<'a, 'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum DocLeaf<'a> {
+    DocRef(Vec<LeafNode<'a>>, Position),
+    Leaf(LeafNode<'a>, Position),
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum LeafNode<'a> {
+    PCData(&'a str),
+    TextTT(&'a str),
+    AutoRef { label: &'a str },
+    ShortRef { sec: &'a str },
+    Obj { name: &'a str },
+}
+
+impl<'a> Api<'a> {
+    /// Parses the IDL of `doc`, which it borrows from.
+    pub fn from_document(doc: &'a roxmltree::Document<'a>) -> Result<Api<'a>, Sel4XmlError> {
+        Api::parse_document(doc, &mut Recovery::strict())
+    }
+
+    pub(crate) fn parse_document(
+        doc: &'a roxmltree::Document<'a>,
+        recovery: &mut Recovery,
+    ) -> Result<Api<'a>, Sel4XmlError> {
+        let element = doc.root_element();
+
+        let name = element.attribute("name");
+        let label_prefix = element.attribute("label_prefix");
+        let mut children = Vec::new();
+        for child in element
+            .children()
+            .filter(|child| !is_whitespace(*child) && !child.is_comment())
+        {
+            let child = ApiElement::from_node(child, recovery);
+            if let Some(child) = recovery.skip(child)? {
+                children.push(child)
+            }
+        }
+
+        Ok(Api {
+            position: position(element),
+            name,
+            label_prefix,
+            children,
+            node: element,
+        })
+    }
+
+    pub fn into_owned(self) -> owned::Api {
+        let text = self.node.document().input_text();
+        owned::Api {
+            position: self.position,
+            name: self.name.map(str::to_string),
+            label_prefix: self.label_prefix.map(str::to_string),
+            children: self
+                .children
+                .into_iter()
+                .map(ApiElement::into_owned)
+                .collect(),
+            formatting: Formatting {
+                prolog: text[..self.node.range().start].to_string(),
+                epilogue: text[self.node.range().end..].to_string(),
+                ..formatting(self.node)
+            },
+        }
+    }
+}
+
+impl<'a> ApiElement<'a> {
+    pub(crate) fn from_node(
+        node: roxmltree::Node<'a, 'a>,
+        recovery: &mut Recovery,
+    ) -> Result<ApiElement<'a>, Sel4XmlError> {
+        let tag = node.tag_name().name();
+        if tag.eq_ignore_ascii_case("struct") {
+            let name = node.req_attr("name")?;
+            let mut members = Vec::new();
+
+            for child in node.children() {
+                if let roxmltree::NodeType::Element = child.node_type() {
+                    let child_name = child.req_attr("name")?;
+                    members.push(child_name);
+                }
+            }
+
+            Ok(ApiElement::StructElem {
+                name,
+                members,
+                node,
+            })
+        } else if tag.eq_ignore_ascii_case("interface") {
+            let name = node.req_attr("name")?;
+            let manual_name = node.opt_attr("manual_name");
+            let cap_desc = node.opt_attr("cap_description");
+            let mut methods = Vec::new();
+            for child in node
+                .children()
+                .filter(|child| !is_whitespace(*child) && !child.is_comment())
+            {
+                let child = Method::from_node(child, recovery);
+                if let Some(child) = recovery.skip(child)? {
+                    methods.push(child);
+                }
+            }
+
+            Ok(ApiElement::Interface {
+                name,
+                manual_name,
+                cap_desc,
+                methods,
+                node,
+            })
+        } else {
+            Err(unsupported_node(node))
+        }
+    }
+
+    pub fn into_owned(self) -> owned::ApiElement {
+        match self {
+            ApiElement::StructElem {
+                name,
+                members,
+                node,
+            } => owned::ApiElement::StructElem {
+                name: name.to_string(),
+                members: members.into_iter().map(str::to_string).collect(),
+                formatting: formatting(node),
+            },
+            ApiElement::Interface {
+                name,
+                manual_name,
+                cap_desc,
+                methods,
+                node,
+            } => owned::ApiElement::Interface {
+                name: name.to_string(),
+                manual_name: manual_name.map(str::to_string),
+                cap_desc: cap_desc.map(str::to_string),
+                methods: methods.into_iter().map(Method::into_owned).collect(),
+                formatting: formatting(node),
+            },
+        }
+    }
+}
+
+impl<'a> Method<'a> {
+    pub(crate) fn from_node(
+        node: roxmltree::Node<'a, 'a>,
+        recovery: &mut Recovery,
+    ) -> Result<Method<'a>, Sel4XmlError> {
+        let method_node = node;
+        // handle attributes
+        let name = node.req_attr("name")?;
+        let id = node.req_attr("id")?;
+        let condition = node.opt_attr("condition");
+        let manual_name = node.opt_attr("manual_name");
+        let manual_label = node.opt_attr("manual_label");
+
+        // handle children
+        let mut brief = Vec::new();
+        let node = node.first_child();
+        let node = if let Some(node) = skip_irrelevant(node) {
+            take_leaves_from_element(node, "brief", &mut brief, recovery)?
+        } else {
+            None
+        };
+
+        let mut description = Vec::new();
+        let node = if let Some(node) = skip_irrelevant(node) {
+            take_leaves_from_element(node, "description", &mut description, recovery)?
+        } else {
+            None
+        };
+
+        let mut return_value = Vec::new();
+        let node = if let Some(node) = skip_irrelevant(node) {
+            take_return_value_from_element(node, &mut return_value, recovery)?
+        } else {
+            None
+        };
+
+        let mut cap_param = None;
+        let node = if let Some(node) = skip_irrelevant(node) {
+            match node.node_type() {
+                roxmltree::NodeType::Element
+                    if node.tag_name().name().eq_ignore_ascii_case("cap_param") =>
+                {
+                    cap_param = node
+                        .opt_attr("append_description")
+                        .map(|append_description| CapParam {
+                            append_description,
+                            node,
+                        });
+                    node.next_sibling()
+                }
+                _ => Some(node),
+            }
+        } else {
+            None
+        };
+
+        let mut params = Vec::new();
+        let mut node = node;
+        while let Some(a_node) = skip_irrelevant(node) {
+            if a_node.node_type() == roxmltree::NodeType::Element
+                && a_node.tag_name().name().eq_ignore_ascii_case("param")
+            {
+                let param = Param::from_node(a_node, recovery);
+                if let Some(param) = recovery.skip(param)? {
+                    params.push(param);
+                }
+                node = a_node.next_sibling();
+            } else {
+                break;
+            }
+        }
+
+        let mut errors = Vec::new();
+        while let Some(a_node) = skip_irrelevant(node) {
+            let error = ErrorElement::from_node(a_node, recovery);
+            if let Some(error) = recovery.skip(error)? {
+                errors.push(error);
+            }
+            node = a_node.next_sibling();
+        }
+
+        Ok(Method {
+            position: position(method_node),
+            name,
+            id,
+            condition,
+            manual_name,
+            manual_label,
+            brief,
+            description,
+            return_value,
+            cap_param,
+            params,
+            errors,
+            node: method_node,
+        })
+    }
+
+    pub fn into_owned(self) -> owned::Method {
+        owned::Method {
+            position: self.position,
+            name: self.name.to_string(),
+            id: self.id.to_string(),
+            condition: self.condition.map(str::to_string),
+            manual_name: self.manual_name.map(str::to_string),
+            manual_label: self.manual_label.map(str::to_string),
+            brief: self.brief.into_iter().map(DocLeaf::into_owned).collect(),
+            description: self
+                .description
+                .into_iter()
+                .map(DocLeaf::into_owned)
+                .collect(),
+            return_value: self
+                .return_value
+                .into_iter()
+                .map(Return::into_owned)
+                .collect(),
+            cap_param: self.cap_param.map(|cap_param| owned::CapParam {
+                append_description: cap_param.append_description.to_string(),
+                formatting: formatting(cap_param.node),
+            }),
+            params: self.params.into_iter().map(Param::into_owned).collect(),
+            errors: self
+                .errors
+                .into_iter()
+                .map(ErrorElement::into_owned)
+                .collect(),
+            formatting: formatting(self.node),
+        }
+    }
+}
+
+impl<'a> Param<'a> {
+    pub(crate) fn from_node(
+        node: roxmltree::Node<'a, 'a>,
+        recovery: &mut Recovery,
+    ) -> Result<Param<'a>, Sel4XmlError> {
+        if !node.is_element() || !node.tag_name().name().eq_ignore_ascii_case("param") {
+            return Err(unsupported_node(node));
+        }
+        let typ = node.req_attr("type")?;
+        let name = node.req_attr("name")?;
+        let dir = node.req_attr("dir")?;
+        let description_attr = node.opt_attr("description");
+        let mut description = Vec::new();
+        if let Some(description_attr) = description_attr {
+            description.push(DocLeaf::Leaf(
+                LeafNode::PCData(description_attr),
+                attribute_position(node, "description"),
+            ));
+        }
+
+        let mut child = if let Some(child) = skip_irrelevant(node.first_child()) {
+            take_leaves_from_element(child, "description", &mut description, recovery)?
+        } else {
+            None
+        };
+        let mut errors = Vec::new();
+        while let Some(a_child) = skip_irrelevant(child) {
+            let error = ErrorElement::from_node(a_child, recovery);
+            if let Some(error) = recovery.skip(error)? {
+                errors.push(error);
+            }
+            child = a_child.next_sibling();
+        }
+
+        Ok(Param {
+            position: position(node),
+            typ,
+            name,
+            dir,
+            description,
+            description_attribute: description_attr.is_some(),
+            errors,
+            node,
+        })
+    }
+
+    pub fn into_owned(self) -> owned::Param {
+        owned::Param {
+            position: self.position,
+            typ: self.typ.to_string(),
+            name: self.name.to_string(),
+            dir: self.dir.to_string(),
+            description: self
+                .description
+                .into_iter()
+                .map(DocLeaf::into_owned)
+                .collect(),
+            description_attribute: self.description_attribute,
+            errors: self
+                .errors
+                .into_iter()
+                .map(ErrorElement::into_owned)
+                .collect(),
+            formatting: formatting(self.node),
+        }
+    }
+}
+
+impl<'a> ErrorElement<'a> {
+    pub(crate) fn from_node(
+        node: roxmltree::Node<'a, 'a>,
+        recovery: &mut Recovery,
+    ) -> Result<ErrorElement<'a>, Sel4XmlError> {
+        if !node.is_element() || !node.tag_name().name().eq_ignore_ascii_case("error") {
+            return Err(unsupported_node(node));
+        }
+        let name = node.req_attr("name")?;
+        let description_attr = node.opt_attr("description");
+        let mut description = Vec::new();
+
+        if let Some(description_attr) = description_attr {
+            description.push(DocLeaf::Leaf(
+                LeafNode::PCData(description_attr),
+                attribute_position(node, "description"),
+            ))
+        }
+
+        if let Some(child) = skip_irrelevant(node.first_child()) {
+            let _child =
+                take_leaves_from_element(child, "description", &mut description, recovery)?;
+        }
+
+        Ok(ErrorElement {
+            position: position(node),
+            name,
+            description,
+            description_attribute: description_attr.is_some(),
+            node,
+        })
+    }
+
+    pub fn into_owned(self) -> owned::ErrorElement {
+        owned::ErrorElement {
+            position: self.position,
+            name: self.name.to_string(),
+            description: self
+                .description
+                .into_iter()
+                .map(DocLeaf::into_owned)
+                .collect(),
+            description_attribute: self.description_attribute,
+            formatting: formatting(self.node),
+        }
+    }
+}
+
+impl<'a> TryFrom<roxmltree::Node<'a, 'a>> for Return<'a> {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'a>) -> Result<Return<'a>, Sel4XmlError> {
+        if node.is_element() && node.tag_name().name().eq_ignore_ascii_case("errorenumdesc") {
+            Ok(Return::ErrorEnumDesc)
+        } else {
+            Ok(Return::Leaves(DocLeaf::try_from(node)?))
+        }
+    }
+}
+
+impl Return<'_> {
+    pub fn into_owned(self) -> owned::Return {
+        match self {
+            Return::ErrorEnumDesc => owned::Return::ErrorEnumDesc,
+            Return::Leaves(leaf) => owned::Return::Leaves(leaf.into_owned()),
+        }
+    }
+}
+
+impl<'a> TryFrom<roxmltree::Node<'a, 'a>> for DocLeaf<'a> {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'a>) -> Result<DocLeaf<'a>, Sel4XmlError> {
+        Ok(
+            if node.is_element() && node.tag_name().name().eq_ignore_ascii_case("docref") {
+                let mut leaves = Vec::new();
+
+                for leaf in node.children() {
+                    let leaf = LeafNode::try_from(leaf)?;
+                    leaves.push(leaf)
+                }
+
+                DocLeaf::DocRef(leaves, position(node))
+            } else {
+                DocLeaf::Leaf(LeafNode::try_from(node)?, position(node))
+            },
+        )
+    }
+}
+
+impl DocLeaf<'_> {
+    pub fn into_owned(self) -> owned::DocLeaf {
+        match self {
+            DocLeaf::DocRef(leaves, position) => owned::DocLeaf::DocRef(
+                leaves.into_iter().map(LeafNode::into_owned).collect(),
+                position,
+            ),
+            DocLeaf::Leaf(leaf, position) => owned::DocLeaf::Leaf(leaf.into_owned(), position),
+        }
+    }
+}
+
+impl<'a> TryFrom<roxmltree::Node<'a, 'a>> for LeafNode<'a> {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'a>) -> Result<LeafNode<'a>, Self::Error> {
+        if let Some(text) = node.text().filter(|_| node.is_text()) {
+            return Ok(LeafNode::PCData(text));
+        }
+        let tag_name = node.tag_name().name();
+        match node.node_type() {
+            roxmltree::NodeType::Element if tag_name.eq_ignore_ascii_case("texttt") => {
+                Ok(LeafNode::TextTT(node.req_attr("text")?))
+            }
+            roxmltree::NodeType::Element if tag_name.eq_ignore_ascii_case("shortref") => {
+                Ok(LeafNode::ShortRef {
+                    sec: node.req_attr("sec")?,
+                })
+            }
+            roxmltree::NodeType::Element if tag_name.eq_ignore_ascii_case("autoref") => {
+                Ok(LeafNode::AutoRef {
+                    label: node.req_attr("label")?,
+                })
+            }
+            roxmltree::NodeType::Element if tag_name.eq_ignore_ascii_case("obj") => {
+                Ok(LeafNode::Obj {
+                    name: node.req_attr("name")?,
+                })
+            }
+            _ => Err(unsupported_node(node)),
+        }
+    }
+}
+
+impl LeafNode<'_> {
+    pub fn into_owned(self) -> owned::LeafNode {
+        match self {
+            LeafNode::PCData(text) => owned::LeafNode::PCData(text.to_string()),
+            LeafNode::TextTT(text) => owned::LeafNode::TextTT(text.to_string()),
+            LeafNode::AutoRef { label } => owned::LeafNode::AutoRef {
+                label: label.to_string(),
+            },
+            LeafNode::ShortRef { sec } => owned::LeafNode::ShortRef {
+                sec: sec.to_string(),
+            },
+            LeafNode::Obj { name } => owned::LeafNode::Obj {
+                name: name.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use std::path::Path;
+
+    // A static, as each use of a const may have its own address.
+    static XML: &str = r#"<?xml version="1.0" ?>
+<api name="ObjectApi" label_prefix="seL4_">
+    <struct name="seL4_Pair">
+        <member name="first"/>
+    </struct>
+    <interface name="seL4_TCB" manual_name="TCB">
+        <method id="TCBSetPriority" name="SetPriority">
+            <brief>Change a thread's priority &amp; more</brief>
+            <description>See <docref><autoref label="sec:sched"/></docref></description>
+            <return><errorenumdesc/></return>
+            <cap_param append_description="The thread."/>
+            <param dir="in" name="priority" type="seL4_Word" description="The priority.">
+                <error name="seL4_RangeError"/>
+            </param>
+            <error name="seL4_IllegalOperation"/>
+        </method>
+    </interface>
+</api>
+"#;
+
+    #[test]
+    fn borrows() -> Result<(), TestError> {
+        let doc = roxmltree::Document::parse(XML)?;
+        let api = Api::from_document(&doc)?;
+        let method = match &api.children[1] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        // Attributes and text point into the xml, rather than being copied.
+        let within_xml = |s: &str| XML.as_bytes().as_ptr_range().contains(&s.as_ptr());
+        assert!(within_xml(method.id));
+        assert!(within_xml(method.params[0].typ));
+        assert!(matches!(
+            method.params[0].description[0],
+            DocLeaf::Leaf(LeafNode::PCData(text), _) if within_xml(text)
+        ));
+        assert_eq!(method.params[0].errors[0].name, "seL4_RangeError");
+        // Except where entities had to be replaced.
+        assert!(matches!(
+            method.brief[0],
+            DocLeaf::Leaf(LeafNode::PCData(text), _) if text == "Change a thread's priority & more"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn into_owned() -> Result<(), TestError> {
+        let doc = roxmltree::Document::parse(XML)?;
+        let owned = Api::from_document(&doc)?.into_owned();
+        let parsed = owned::Api::parse(Path::new("sel4.xml"), XML)?;
+        assert_eq!(format!("{:?}", owned), format!("{:?}", parsed));
+        assert_eq!(owned.to_xml(), XML);
+        Ok(())
+    }
+}
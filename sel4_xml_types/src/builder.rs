@@ -0,0 +1,508 @@
+use crate::invocations::*;
+use std::collections::HashSet;
+use thiserror::Error;
+
+//
+// Builders for the invocation IDL model, for tests and for generating
+// IDL from sources other than the xml.
+//
+// `build` checks what the xml parser can't get wrong by construction,
+// or what sel4_idl.dtd leaves to the generators: invocation labels are
+// generated from the method ids, so they must be unique.
+//
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("{element} has an empty '{attribute}'")]
+    Empty {
+        element: String,
+        attribute: &'static str,
+    },
+    #[error("param '{param}' has dir '{dir}', expected 'in' or 'out'")]
+    Dir { param: String, dir: String },
+    #[error("method '{method}' has more than one param '{param}'")]
+    DuplicateParam { method: String, param: String },
+    #[error("more than one method has the id '{0}'")]
+    DuplicateMethod(String),
+    #[error("more than one interface or struct is named '{0}'")]
+    DuplicateName(String),
+}
+
+fn text(text: String) -> Vec<DocLeaf> {
+    vec![DocLeaf::Leaf(LeafNode::PCData(text), Position::default())]
+}
+
+fn non_empty(element: &str, attribute: &'static str, value: &str) -> Result<(), BuildError> {
+    if value.is_empty() {
+        Err(BuildError::Empty {
+            element: element.to_string(),
+            attribute,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ErrorBuilder {
+    name: String,
+    description: Option<String>,
+}
+
+impl ErrorBuilder {
+    pub fn new(name: impl Into<String>) -> ErrorBuilder {
+        ErrorBuilder {
+            name: name.into(),
+            description: None,
+        }
+    }
+
+    /// The `description` attribute.
+    pub fn description(self, description: impl Into<String>) -> ErrorBuilder {
+        ErrorBuilder {
+            description: Some(description.into()),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<ErrorElement, BuildError> {
+        non_empty("error", "name", &self.name)?;
+        Ok(ErrorElement {
+            position: Position::default(),
+            name: self.name,
+            description_attribute: self.description.is_some(),
+            description: self.description.map_or_else(Vec::new, text),
+            formatting: Formatting::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamBuilder {
+    name: String,
+    typ: String,
+    dir: String,
+    description: Option<String>,
+    errors: Vec<ErrorBuilder>,
+}
+
+impl ParamBuilder {
+    /// `dir` is either `in` or `out`.
+    pub fn new(
+        name: impl Into<String>,
+        typ: impl Into<String>,
+        dir: impl Into<String>,
+    ) -> ParamBuilder {
+        ParamBuilder {
+            name: name.into(),
+            typ: typ.into(),
+            dir: dir.into(),
+            description: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The `description` attribute.
+    pub fn description(self, description: impl Into<String>) -> ParamBuilder {
+        ParamBuilder {
+            description: Some(description.into()),
+            ..self
+        }
+    }
+
+    pub fn error(mut self, error: ErrorBuilder) -> ParamBuilder {
+        self.errors.push(error);
+        self
+    }
+
+    pub fn build(self) -> Result<Param, BuildError> {
+        non_empty("param", "name", &self.name)?;
+        non_empty(&format!("param '{}'", self.name), "type", &self.typ)?;
+        if self.dir != "in" && self.dir != "out" {
+            return Err(BuildError::Dir {
+                param: self.name,
+                dir: self.dir,
+            });
+        }
+        Ok(Param {
+            position: Position::default(),
+            typ: self.typ,
+            name: self.name,
+            dir: self.dir,
+            description_attribute: self.description.is_some(),
+            description: self.description.map_or_else(Vec::new, text),
+            errors: self
+                .errors
+                .into_iter()
+                .map(ErrorBuilder::build)
+                .collect::<Result<_, _>>()?,
+            formatting: Formatting::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodBuilder {
+    id: String,
+    name: String,
+    condition: Option<String>,
+    manual_name: Option<String>,
+    manual_label: Option<String>,
+    brief: Option<String>,
+    description: Option<String>,
+    cap_param: Option<String>,
+    params: Vec<ParamBuilder>,
+    errors: Vec<ErrorBuilder>,
+}
+
+impl MethodBuilder {
+    /// `id` is the invocation label e.g. `TCBConfigure`, `name` the
+    /// method of the interface e.g. `Configure`.
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            id: id.into(),
+            name: name.into(),
+            condition: None,
+            manual_name: None,
+            manual_label: None,
+            brief: None,
+            description: None,
+            cap_param: None,
+            params: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// A C preprocessor condition e.g. `defined(CONFIG_KERNEL_MCS)`
+    pub fn condition(self, condition: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            condition: Some(condition.into()),
+            ..self
+        }
+    }
+
+    pub fn manual_name(self, manual_name: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            manual_name: Some(manual_name.into()),
+            ..self
+        }
+    }
+
+    pub fn manual_label(self, manual_label: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            manual_label: Some(manual_label.into()),
+            ..self
+        }
+    }
+
+    pub fn brief(self, brief: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            brief: Some(brief.into()),
+            ..self
+        }
+    }
+
+    pub fn description(self, description: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            description: Some(description.into()),
+            ..self
+        }
+    }
+
+    /// Appended to the description of the invoked capability.
+    pub fn cap_param(self, append_description: impl Into<String>) -> MethodBuilder {
+        MethodBuilder {
+            cap_param: Some(append_description.into()),
+            ..self
+        }
+    }
+
+    pub fn param(mut self, param: ParamBuilder) -> MethodBuilder {
+        self.params.push(param);
+        self
+    }
+
+    pub fn error(mut self, error: ErrorBuilder) -> MethodBuilder {
+        self.errors.push(error);
+        self
+    }
+
+    pub fn build(self) -> Result<Method, BuildError> {
+        non_empty("method", "id", &self.id)?;
+        non_empty(&format!("method '{}'", self.id), "name", &self.name)?;
+        let mut names = HashSet::new();
+        let mut params = Vec::new();
+        for param in self.params {
+            if !names.insert(param.name.clone()) {
+                return Err(BuildError::DuplicateParam {
+                    method: self.id,
+                    param: param.name,
+                });
+            }
+            params.push(param.build()?);
+        }
+        Ok(Method {
+            position: Position::default(),
+            name: self.name,
+            id: self.id,
+            condition: self.condition,
+            manual_name: self.manual_name,
+            manual_label: self.manual_label,
+            brief: self.brief.map_or_else(Vec::new, text),
+            description: self.description.map_or_else(Vec::new, text),
+            return_value: Vec::new(),
+            cap_param: self.cap_param.map(|append_description| CapParam {
+                append_description,
+                formatting: Formatting::default(),
+            }),
+            params,
+            errors: self
+                .errors
+                .into_iter()
+                .map(ErrorBuilder::build)
+                .collect::<Result<_, _>>()?,
+            formatting: Formatting::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceBuilder {
+    name: String,
+    manual_name: Option<String>,
+    cap_desc: Option<String>,
+    methods: Vec<MethodBuilder>,
+}
+
+impl InterfaceBuilder {
+    /// `name` is the capability type e.g. `seL4_TCB`
+    pub fn new(name: impl Into<String>) -> InterfaceBuilder {
+        InterfaceBuilder {
+            name: name.into(),
+            manual_name: None,
+            cap_desc: None,
+            methods: Vec::new(),
+        }
+    }
+
+    pub fn manual_name(self, manual_name: impl Into<String>) -> InterfaceBuilder {
+        InterfaceBuilder {
+            manual_name: Some(manual_name.into()),
+            ..self
+        }
+    }
+
+    /// The `cap_description` of the invoked capability.
+    pub fn cap_description(self, cap_desc: impl Into<String>) -> InterfaceBuilder {
+        InterfaceBuilder {
+            cap_desc: Some(cap_desc.into()),
+            ..self
+        }
+    }
+
+    pub fn method(mut self, method: MethodBuilder) -> InterfaceBuilder {
+        self.methods.push(method);
+        self
+    }
+
+    pub fn build(self) -> Result<ApiElement, BuildError> {
+        non_empty("interface", "name", &self.name)?;
+        let methods = self
+            .methods
+            .into_iter()
+            .map(MethodBuilder::build)
+            .collect::<Result<Vec<_>, _>>()?;
+        unique_ids(&methods)?;
+        Ok(ApiElement::Interface {
+            name: self.name,
+            manual_name: self.manual_name,
+            cap_desc: self.cap_desc,
+            methods,
+            formatting: Formatting::default(),
+        })
+    }
+}
+
+fn unique_ids<'a>(methods: impl IntoIterator<Item = &'a Method>) -> Result<(), BuildError> {
+    let mut ids = HashSet::new();
+    for method in methods {
+        if !ids.insert(method.id.as_str()) {
+            return Err(BuildError::DuplicateMethod(method.id.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiBuilder {
+    name: Option<String>,
+    label_prefix: Option<String>,
+    children: Vec<Result<ApiElement, BuildError>>,
+}
+
+impl ApiBuilder {
+    pub fn new() -> ApiBuilder {
+        ApiBuilder::default()
+    }
+
+    /// e.g. `ObjectApi`
+    pub fn name(self, name: impl Into<String>) -> ApiBuilder {
+        ApiBuilder {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// e.g. `seL4_`
+    pub fn label_prefix(self, label_prefix: impl Into<String>) -> ApiBuilder {
+        ApiBuilder {
+            label_prefix: Some(label_prefix.into()),
+            ..self
+        }
+    }
+
+    /// A `<struct>` with a member for each word.
+    pub fn structure(mut self, name: impl Into<String>, members: &[&str]) -> ApiBuilder {
+        let name = name.into();
+        let structure = non_empty("struct", "name", &name).map(|()| ApiElement::StructElem {
+            name,
+            members: members.iter().map(|member| member.to_string()).collect(),
+            formatting: Formatting::default(),
+        });
+        self.children.push(structure);
+        self
+    }
+
+    pub fn interface(mut self, interface: InterfaceBuilder) -> ApiBuilder {
+        self.children.push(interface.build());
+        self
+    }
+
+    /// Method ids must be unique across the interfaces, and the names of
+    /// interfaces and structs across both.
+    pub fn build(self) -> Result<Api, BuildError> {
+        let children = self.children.into_iter().collect::<Result<Vec<_>, _>>()?;
+        let mut names = HashSet::new();
+        for child in &children {
+            let name = match child {
+                ApiElement::StructElem { name, .. } | ApiElement::Interface { name, .. } => name,
+            };
+            if !names.insert(name.as_str()) {
+                return Err(BuildError::DuplicateName(name.clone()));
+            }
+        }
+        unique_ids(children.iter().flat_map(|child| match child {
+            ApiElement::Interface { methods, .. } => methods.as_slice(),
+            ApiElement::StructElem { .. } => &[],
+        }))?;
+        Ok(Api {
+            position: Position::default(),
+            name: self.name,
+            label_prefix: self.label_prefix,
+            children,
+            formatting: Formatting::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use std::path::Path;
+
+    fn tcb() -> InterfaceBuilder {
+        InterfaceBuilder::new("seL4_TCB").manual_name("TCB").method(
+            MethodBuilder::new("TCBSetPriority", "SetPriority")
+                .brief("Change a thread's priority")
+                .param(
+                    ParamBuilder::new("authority", "seL4_TCB", "in")
+                        .description("The TCB whose MCP is used."),
+                )
+                .param(
+                    ParamBuilder::new("priority", "seL4_Word", "in")
+                        .error(ErrorBuilder::new("seL4_RangeError")),
+                )
+                .error(ErrorBuilder::new("seL4_IllegalOperation").description("Never")),
+        )
+    }
+
+    #[test]
+    fn build() -> Result<(), TestError> {
+        let api = ApiBuilder::new()
+            .name("ObjectApi")
+            .label_prefix("seL4_")
+            .structure("seL4_Pair", &["first", "second"])
+            .interface(tcb())
+            .build()?;
+        let xml = api.to_xml();
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" ?>
+<api name="ObjectApi" label_prefix="seL4_">
+    <struct name="seL4_Pair">
+        <member name="first"/>
+        <member name="second"/>
+    </struct>
+    <interface name="seL4_TCB" manual_name="TCB">
+        <method id="TCBSetPriority" name="SetPriority">
+            <brief>Change a thread's priority</brief>
+            <param dir="in" name="authority" type="seL4_TCB" description="The TCB whose MCP is used."/>
+            <param dir="in" name="priority" type="seL4_Word">
+                <error name="seL4_RangeError"/>
+            </param>
+            <error name="seL4_IllegalOperation" description="Never"/>
+        </method>
+    </interface>
+</api>
+"#
+        );
+
+        // The parser reads back what was built.
+        let parsed = Api::parse(Path::new("built.xml"), &xml)?;
+        let method = match &parsed.children[1] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        assert_eq!(method.params[1].errors()[0].name(), "seL4_RangeError");
+        assert_eq!(method.errors[0].name(), "seL4_IllegalOperation");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        let duplicate = ApiBuilder::new()
+            .interface(tcb())
+            .interface(
+                InterfaceBuilder::new("seL4_Other")
+                    .method(MethodBuilder::new("TCBSetPriority", "SetPriority")),
+            )
+            .build();
+        assert_eq!(
+            duplicate.unwrap_err(),
+            BuildError::DuplicateMethod("TCBSetPriority".to_string())
+        );
+
+        let dir = MethodBuilder::new("TCBSuspend", "Suspend")
+            .param(ParamBuilder::new("a", "seL4_Word", "inout"))
+            .build();
+        assert_eq!(
+            dir.unwrap_err().to_string(),
+            "param 'a' has dir 'inout', expected 'in' or 'out'"
+        );
+
+        let param = MethodBuilder::new("TCBSuspend", "Suspend")
+            .param(ParamBuilder::new("a", "seL4_Word", "in"))
+            .param(ParamBuilder::new("a", "seL4_Word", "out"))
+            .build();
+        assert!(matches!(
+            param,
+            Err(BuildError::DuplicateParam { ref param, .. }) if param == "a"
+        ));
+
+        let empty = ApiBuilder::new()
+            .interface(InterfaceBuilder::new("seL4_TCB").method(MethodBuilder::new("", "X")))
+            .build();
+        assert_eq!(empty.unwrap_err().to_string(), "method has an empty 'id'");
+    }
+}
@@ -0,0 +1,604 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+//
+// `Method.condition` and `syscalls::Config.condition` contain C preprocessor
+// expressions which the C templates paste into `#if` lines as-is.
+//
+// This parses the subset of the preprocessor expression language
+// which appears in the seL4 xml:
+//   `defined(X)`, `defined X`, identifiers, integer literals,
+//   `!`, `&&`, `||`, `==`, `!=`, `<`, `<=`, `>`, `>=` and parentheses.
+//
+// The resulting AST can be displayed back as a C expression, or rendered
+// into a rust `cfg` predicate through a `CfgMap`.
+//
+
+#[derive(Error, Debug)]
+pub enum ConditionError {
+    #[error("{message} at offset {offset} in condition '{condition}'")]
+    Parse {
+        condition: String,
+        offset: usize,
+        message: String,
+    },
+    #[error("line {0}: expected 'CONDITION => PREDICATE'")]
+    MapLine(usize),
+    #[error(
+        "no cfg predicate for '{0}', give it one with --cfg-map e.g. '{0} => feature = \"...\"'"
+    )]
+    Unmapped(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Condition {
+    /// `defined(NAME)` or `defined NAME`
+    Defined(String),
+    /// The value of a macro, which is `0` when it is not defined.
+    Ident(String),
+    Int(i64),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Compare(Box<Condition>, CompareOp, Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    // Used to build cfg names, `>` isn't something you'd want in a feature name.
+    fn as_word(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "eq",
+            CompareOp::Ne => "ne",
+            CompareOp::Lt => "lt",
+            CompareOp::Le => "le",
+            CompareOp::Gt => "gt",
+            CompareOp::Ge => "ge",
+        }
+    }
+
+    pub fn apply(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ConditionError;
+    fn from_str(s: &str) -> Result<Condition, ConditionError> {
+        let mut parser = Parser {
+            input: s,
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let condition = parser.or()?;
+        match parser.peek() {
+            None => Ok(condition),
+            Some(_) => Err(parser.error("unexpected trailing input")),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn operand(f: &mut fmt::Formatter<'_>, c: &Condition) -> fmt::Result {
+            match c {
+                Condition::And(_) | Condition::Or(_) | Condition::Compare(..) => {
+                    write!(f, "({})", c)
+                }
+                _ => write!(f, "{}", c),
+            }
+        }
+
+        match self {
+            Condition::Defined(name) => write!(f, "defined({})", name),
+            Condition::Ident(name) => write!(f, "{}", name),
+            Condition::Int(n) => write!(f, "{}", n),
+            Condition::Not(c) => {
+                write!(f, "!")?;
+                operand(f, c)
+            }
+            Condition::And(cs) | Condition::Or(cs) => {
+                let sep = if let Condition::And(_) = self {
+                    " && "
+                } else {
+                    " || "
+                };
+                for (i, c) in cs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "{}", sep)?;
+                    }
+                    operand(f, c)?;
+                }
+                Ok(())
+            }
+            Condition::Compare(lhs, op, rhs) => {
+                operand(f, lhs)?;
+                write!(f, " {} ", op.as_str())?;
+                operand(f, rhs)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Int(i64),
+    Not,
+    And,
+    Or,
+    Op(CompareOp),
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token<'_>)>, ConditionError> {
+    let error = |offset, message: &str| ConditionError::Parse {
+        condition: input.to_string(),
+        offset,
+        message: message.to_string(),
+    };
+    let bytes = input.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let two = &bytes[i..(i + 2).min(bytes.len())];
+        let token = match bytes[i] {
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            _ if two == b"&&" => Token::And,
+            _ if two == b"||" => Token::Or,
+            _ if two == b"==" => Token::Op(CompareOp::Eq),
+            _ if two == b"!=" => Token::Op(CompareOp::Ne),
+            _ if two == b"<=" => Token::Op(CompareOp::Le),
+            _ if two == b">=" => Token::Op(CompareOp::Ge),
+            b'!' => Token::Not,
+            b'<' => Token::Op(CompareOp::Lt),
+            b'>' => Token::Op(CompareOp::Gt),
+            c if c.is_ascii_digit() => {
+                while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                // Drop any integer suffix e.g. `1UL`.
+                let text = input[start..i].trim_end_matches(['u', 'U', 'l', 'L']);
+                let value = if let Some(hex) =
+                    text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+                {
+                    i64::from_str_radix(hex, 16)
+                } else {
+                    text.parse()
+                };
+                tokens.push((
+                    start,
+                    Token::Int(value.map_err(|_| error(start, "invalid integer"))?),
+                ));
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push((start, Token::Ident(&input[start..i])));
+                continue;
+            }
+            _ => return Err(error(start, "unexpected character")),
+        };
+        i += match token {
+            Token::LParen | Token::RParen | Token::Not => 1,
+            Token::Op(CompareOp::Lt) | Token::Op(CompareOp::Gt) => 1,
+            _ => 2,
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token<'a>)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> ConditionError {
+        let offset = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.input.len(), |(offset, _)| *offset);
+        ConditionError::Parse {
+            condition: self.input.to_string(),
+            offset,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, token: Token<'a>, message: &str) -> Result<(), ConditionError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, ConditionError> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            terms.push(self.and()?);
+        }
+        Ok(flatten(terms, false))
+    }
+
+    fn and(&mut self) -> Result<Condition, ConditionError> {
+        let mut terms = vec![self.equality()?];
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            terms.push(self.equality()?);
+        }
+        Ok(flatten(terms, true))
+    }
+
+    fn equality(&mut self) -> Result<Condition, ConditionError> {
+        let mut lhs = self.relational()?;
+        while let Some(Token::Op(op @ (CompareOp::Eq | CompareOp::Ne))) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            let rhs = self.relational()?;
+            lhs = Condition::Compare(Box::new(lhs), op, Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn relational(&mut self) -> Result<Condition, ConditionError> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(
+            op @ (CompareOp::Lt | CompareOp::Le | CompareOp::Gt | CompareOp::Ge),
+        )) = self.peek()
+        {
+            let op = *op;
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Condition::Compare(Box::new(lhs), op, Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Condition, ConditionError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            Ok(Condition::Not(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Condition, ConditionError> {
+        match self.peek() {
+            Some(Token::Ident("defined")) => {
+                self.pos += 1;
+                let parens = self.peek() == Some(&Token::LParen);
+                if parens {
+                    self.pos += 1;
+                }
+                let name = match self.peek() {
+                    Some(Token::Ident(name)) => name.to_string(),
+                    _ => return Err(self.error("expected an identifier after 'defined'")),
+                };
+                self.pos += 1;
+                if parens {
+                    self.expect(Token::RParen, "expected ')'")?;
+                }
+                Ok(Condition::Defined(name))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let condition = self.or()?;
+                self.expect(Token::RParen, "expected ')'")?;
+                Ok(condition)
+            }
+            Some(Token::Ident(_)) | Some(Token::Int(_)) => match self.next() {
+                Some(Token::Ident(name)) => Ok(Condition::Ident(name.to_string())),
+                Some(Token::Int(n)) => Ok(Condition::Int(n)),
+                _ => unreachable!(),
+            },
+            Some(_) => Err(self.error("unexpected token")),
+            None => Err(self.error("unexpected end of condition")),
+        }
+    }
+}
+
+// Merges nested `&&`/`||` so that `a && (b && c)` renders as a single `all(..)`.
+fn flatten(terms: Vec<Condition>, and: bool) -> Condition {
+    if terms.len() == 1 {
+        return terms.into_iter().next().unwrap();
+    }
+    let mut flat = Vec::new();
+    for term in terms {
+        match term {
+            Condition::And(inner) if and => flat.extend(inner),
+            Condition::Or(inner) if !and => flat.extend(inner),
+            term => flat.push(term),
+        }
+    }
+    if and {
+        Condition::And(flat)
+    } else {
+        Condition::Or(flat)
+    }
+}
+
+/// Maps the names used in conditions onto rust `cfg` predicates.
+///
+/// By default `defined(CONFIG_KERNEL_MCS)` renders as `feature = "kernel_mcs"`,
+/// the key, prefix stripping and lowercasing are all adjustable, and individual
+/// names or whole sub-expressions can be given explicit predicates.
+#[derive(Debug, Clone)]
+pub struct CfgMap {
+    key: String,
+    strip_prefix: Option<String>,
+    lowercase: bool,
+    overrides: HashMap<String, String>,
+}
+
+impl Default for CfgMap {
+    fn default() -> CfgMap {
+        CfgMap {
+            key: "feature".to_string(),
+            strip_prefix: Some("CONFIG_".to_string()),
+            lowercase: true,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl CfgMap {
+    pub fn new() -> CfgMap {
+        CfgMap::default()
+    }
+
+    /// The cfg key that names are assigned to, `feature` by default.
+    pub fn with_key(mut self, key: &str) -> CfgMap {
+        self.key = key.to_string();
+        self
+    }
+
+    /// The prefix removed from names, `CONFIG_` by default.
+    pub fn with_strip_prefix(mut self, prefix: Option<&str>) -> CfgMap {
+        self.strip_prefix = prefix.map(str::to_string);
+        self
+    }
+
+    pub fn with_lowercase(mut self, lowercase: bool) -> CfgMap {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Render `condition` as `predicate`.
+    ///
+    /// `condition` is either a name such as `CONFIG_KERNEL_MCS`, which applies to
+    /// both `defined(CONFIG_KERNEL_MCS)` and `CONFIG_KERNEL_MCS`, or any other
+    /// condition such as `CONFIG_MAX_NUM_NODES > 1`.
+    pub fn insert(&mut self, condition: &str, predicate: &str) -> Result<(), ConditionError> {
+        let condition: Condition = condition.parse()?;
+        let key = match condition {
+            Condition::Ident(name) => name,
+            condition => condition.to_string(),
+        };
+        self.overrides.insert(key, predicate.to_string());
+        Ok(())
+    }
+
+    /// Reads overrides, one `CONDITION => PREDICATE` per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn parse_overrides(&mut self, text: &str) -> Result<(), ConditionError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (condition, predicate) = line
+                .split_once("=>")
+                .ok_or(ConditionError::MapLine(i + 1))?;
+            self.insert(condition.trim(), predicate.trim())?;
+        }
+        Ok(())
+    }
+
+    fn name(&self, name: &str) -> String {
+        if let Some(predicate) = self.overrides.get(name) {
+            return predicate.clone();
+        }
+        format!("{} = \"{}\"", self.key, self.mangle(name))
+    }
+
+    fn mangle(&self, name: &str) -> String {
+        let name = match &self.strip_prefix {
+            Some(prefix) => name.strip_prefix(prefix.as_str()).unwrap_or(name),
+            None => name,
+        };
+        if self.lowercase {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    // Only names and integers fit into a cfg name, `comparison` is the
+    // condition which needs an override otherwise.
+    fn operand(&self, c: &Condition, comparison: &Condition) -> Result<String, ConditionError> {
+        match c {
+            Condition::Ident(name) | Condition::Defined(name) => Ok(self.mangle(name)),
+            Condition::Int(n) => Ok(n.to_string()),
+            _ => Err(ConditionError::Unmapped(comparison.to_string())),
+        }
+    }
+
+    /// Renders `condition` as the contents of a `#[cfg(...)]` attribute.
+    ///
+    /// Comparisons of anything but names and integers have no cfg predicate
+    /// unless one is inserted for them.
+    pub fn render(&self, condition: &Condition) -> Result<String, ConditionError> {
+        if let Some(predicate) = self.overrides.get(&condition.to_string()) {
+            return Ok(predicate.clone());
+        }
+
+        Ok(match condition {
+            // Kernel configuration booleans are either defined as 1 or left undefined.
+            Condition::Defined(name) | Condition::Ident(name) => self.name(name),
+            Condition::Int(0) => "any()".to_string(),
+            Condition::Int(_) => "all()".to_string(),
+            Condition::Not(c) => format!("not({})", self.render(c)?),
+            Condition::And(cs) => format!(
+                "all({})",
+                cs.iter()
+                    .map(|c| self.render(c))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            ),
+            Condition::Or(cs) => format!(
+                "any({})",
+                cs.iter()
+                    .map(|c| self.render(c))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            ),
+            Condition::Compare(lhs, op, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+                (Condition::Int(lhs), Condition::Int(rhs)) => {
+                    self.render(&Condition::Int(op.apply(*lhs, *rhs) as i64))?
+                }
+                (lhs, rhs) => format!(
+                    "{} = \"{}_{}_{}\"",
+                    self.key,
+                    self.operand(lhs, condition)?,
+                    op.as_word(),
+                    self.operand(rhs, condition)?
+                ),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() -> Result<(), ConditionError> {
+        let c: Condition = "defined(CONFIG_KERNEL_MCS) && !defined(CONFIG_X)".parse()?;
+        assert_eq!(
+            c,
+            Condition::And(vec![
+                Condition::Defined("CONFIG_KERNEL_MCS".to_string()),
+                Condition::Not(Box::new(Condition::Defined("CONFIG_X".to_string()))),
+            ])
+        );
+
+        let c: Condition = "(!defined CONFIG_KERNEL_MCS) && CONFIG_MAX_NUM_NODES > 1".parse()?;
+        assert_eq!(
+            c.to_string(),
+            "!defined(CONFIG_KERNEL_MCS) && (CONFIG_MAX_NUM_NODES > 1)"
+        );
+
+        let c: Condition = "defined A || defined B && (defined C || defined D)".parse()?;
+        assert_eq!(
+            c.to_string(),
+            "defined(A) || (defined(B) && (defined(C) || defined(D)))"
+        );
+
+        assert!("defined(".parse::<Condition>().is_err());
+        assert!("A B".parse::<Condition>().is_err());
+        assert!("A $ B".parse::<Condition>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn render() -> Result<(), ConditionError> {
+        let mut map = CfgMap::new();
+        let c: Condition = "defined(CONFIG_KERNEL_MCS) && !defined(CONFIG_X)".parse()?;
+        assert_eq!(
+            map.render(&c)?,
+            r#"all(feature = "kernel_mcs", not(feature = "x"))"#
+        );
+
+        let c: Condition = "(!defined CONFIG_KERNEL_MCS) && CONFIG_MAX_NUM_NODES > 1".parse()?;
+        assert_eq!(
+            map.render(&c)?,
+            r#"all(not(feature = "kernel_mcs"), feature = "max_num_nodes_gt_1")"#
+        );
+
+        map.parse_overrides(
+            r#"
+            # comments are ignored
+            CONFIG_KERNEL_MCS => sel4_mcs
+            CONFIG_MAX_NUM_NODES>1 => feature = "smp"
+            "#,
+        )?;
+        assert_eq!(map.render(&c)?, r#"all(not(sel4_mcs), feature = "smp")"#);
+
+        let map = CfgMap::new()
+            .with_key("sel4_config")
+            .with_strip_prefix(None)
+            .with_lowercase(false);
+        let c: Condition = "defined CONFIG_PRINTING || 0".parse()?;
+        assert_eq!(
+            map.render(&c)?,
+            r#"any(sel4_config = "CONFIG_PRINTING", any())"#
+        );
+
+        let mut map = CfgMap::new();
+        let c: Condition = "!((CONFIG_A && CONFIG_B) == 1)".parse()?;
+        assert!(matches!(
+            map.render(&c),
+            Err(ConditionError::Unmapped(name)) if name == "(CONFIG_A && CONFIG_B) == 1"
+        ));
+        map.insert("(CONFIG_A && CONFIG_B) == 1", "feature = \"a_and_b\"")?;
+        assert_eq!(map.render(&c)?, r#"not(feature = "a_and_b")"#);
+        Ok(())
+    }
+}
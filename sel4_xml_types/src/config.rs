@@ -0,0 +1,444 @@
+use crate::condition::{Condition, ConditionError};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error;
+
+//
+// The values of the kernel configuration options for a particular build,
+// as the C preprocessor would see them when it includes gen_config.h.
+//
+// Boolean options which are enabled are defined as `1`, disabled ones are
+// left undefined, other options are defined to their value.
+//
+// These can be loaded from the gen_config.h or gen_config.json written by
+// the seL4 build, or from the CMakeCache.txt of the build directory.
+// Since the CMake cache uses CMake variable names rather than the names of
+// the macros only the options listed in `CMAKE_CONFIG_NAMES` are picked up
+// from there, so prefer the gen_config files where they exist.
+//
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Condition(#[from] ConditionError),
+    #[error("line {0}: unrecognised line")]
+    Syntax(usize),
+    #[error("'{0}' is defined as '{1}' which is not an integer")]
+    NotAnInteger(String, String),
+    #[error("no kernel configuration found in {0}")]
+    NotFound(PathBuf),
+}
+
+/// CMake cache variables and the configuration macros they produce.
+pub const CMAKE_CONFIG_NAMES: &[(&str, &str)] = &[
+    ("KernelIsMCS", "CONFIG_KERNEL_MCS"),
+    ("KernelMaxNumNodes", "CONFIG_MAX_NUM_NODES"),
+    ("KernelWordSize", "CONFIG_WORD_SIZE"),
+    ("KernelPrinting", "CONFIG_PRINTING"),
+    ("KernelDebugBuild", "CONFIG_DEBUG_BUILD"),
+    ("KernelVerificationBuild", "CONFIG_VERIFICATION_BUILD"),
+    ("KernelHardwareDebugAPI", "CONFIG_HARDWARE_DEBUG_API"),
+    (
+        "KernelDangerousCodeInjection",
+        "CONFIG_DANGEROUS_CODE_INJECTION",
+    ),
+    ("KernelSetTLSBaseSelf", "CONFIG_SET_TLS_BASE_SELF"),
+    ("KernelEnableBenchmarks", "CONFIG_ENABLE_BENCHMARKS"),
+    (
+        "KernelInvocationReportErrorIPC",
+        "CONFIG_KERNEL_INVOCATION_REPORT_ERROR_IPC",
+    ),
+    (
+        "KernelArmHypervisorSupport",
+        "CONFIG_ARM_HYPERVISOR_SUPPORT",
+    ),
+    ("KernelArmSMMU", "CONFIG_ARM_SMMU"),
+    ("KernelArmGicV3", "CONFIG_ARM_GIC_V3_SUPPORT"),
+    ("KernelAllowSMCCalls", "CONFIG_ALLOW_SMC_CALLS"),
+    ("KernelArmExportPCNTUser", "CONFIG_EXPORT_PCNT_USER"),
+    ("KernelArmExportVCNTUser", "CONFIG_EXPORT_VCNT_USER"),
+    ("KernelIOMMU", "CONFIG_IOMMU"),
+    ("KernelVTX", "CONFIG_VTX"),
+    ("KernelX86DangerousMSR", "CONFIG_KERNEL_X86_DANGEROUS_MSR"),
+];
+
+/// CMake choice variables, and the macro defined for each of their values.
+pub const CMAKE_CONFIG_CHOICES: &[(&str, &str, &str)] = &[
+    ("KernelArch", "arm", "CONFIG_ARCH_ARM"),
+    ("KernelArch", "riscv", "CONFIG_ARCH_RISCV"),
+    ("KernelArch", "x86", "CONFIG_ARCH_X86"),
+    ("KernelSel4Arch", "aarch32", "CONFIG_ARCH_AARCH32"),
+    ("KernelSel4Arch", "aarch64", "CONFIG_ARCH_AARCH64"),
+    ("KernelSel4Arch", "ia32", "CONFIG_ARCH_IA32"),
+    ("KernelSel4Arch", "x86_64", "CONFIG_ARCH_X86_64"),
+    ("KernelSel4Arch", "riscv32", "CONFIG_ARCH_RISCV32"),
+    ("KernelSel4Arch", "riscv64", "CONFIG_ARCH_RISCV64"),
+];
+
+// Where the seL4 build leaves its configuration, relative to the build directory.
+const BUILD_DIR_CANDIDATES: &[&str] = &[
+    "gen_config.json",
+    "gen_config.h",
+    "kernel/gen_config/kernel/gen_config.json",
+    "kernel/gen_config/kernel/gen_config.h",
+    "gen_config/kernel/gen_config.json",
+    "gen_config/kernel/gen_config.h",
+    "CMakeCache.txt",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelConfig {
+    defines: BTreeMap<String, String>,
+}
+
+impl KernelConfig {
+    pub fn new() -> KernelConfig {
+        KernelConfig::default()
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.defines.remove(name);
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.defines.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.defines.get(name).map(String::as_str)
+    }
+
+    pub fn defines(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.defines.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Loads `path`, which is either one of the files the seL4 build writes its
+    /// configuration to, or a build directory containing one of them.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KernelConfig, ConfigError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            return KernelConfig::from_build_dir(path);
+        }
+
+        let text = fs::read_to_string(path)?;
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some("CMakeCache.txt") => KernelConfig::from_cmake_cache(&text),
+            Some(name) if name.ends_with(".json") => KernelConfig::from_gen_config_json(&text),
+            _ => KernelConfig::from_gen_config_h(&text),
+        }
+    }
+
+    pub fn from_build_dir<P: AsRef<Path>>(dir: P) -> Result<KernelConfig, ConfigError> {
+        KernelConfig::load(build_dir_file(dir.as_ref())?)
+    }
+
+    /// The file `load` reads for `path`, which is the gen_config.h,
+    /// gen_config.json or CMakeCache.txt in it for a build directory.
+    pub fn resolve<P: AsRef<Path>>(path: P) -> Result<PathBuf, ConfigError> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            build_dir_file(path)
+        } else {
+            Ok(path.to_path_buf())
+        }
+    }
+
+    /// Reads the `#define CONFIG_FOO value` lines of a gen_config.h
+    pub fn from_gen_config_h(text: &str) -> Result<KernelConfig, ConfigError> {
+        let mut config = KernelConfig::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if let Some(define) = line.strip_prefix("#define") {
+                let mut define = define.trim().splitn(2, char::is_whitespace);
+                let name = define.next().filter(|name| !name.is_empty());
+                let name = name.ok_or(ConfigError::Syntax(i + 1))?;
+                let value = define.next().unwrap_or("").trim();
+                config.define(name, value);
+            } else if line.is_empty()
+                || line.starts_with("/*")
+                || line.starts_with("//")
+                || line.starts_with("#pragma")
+            {
+                continue;
+            } else {
+                return Err(ConfigError::Syntax(i + 1));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads a gen_config.json, where keys are the option names with or
+    /// without their `CONFIG_` prefix.
+    pub fn from_gen_config_json(text: &str) -> Result<KernelConfig, ConfigError> {
+        let values: BTreeMap<String, serde_json::Value> = serde_json::from_str(text)?;
+        let mut config = KernelConfig::new();
+        for (name, value) in values {
+            let name = if name.starts_with("CONFIG_") {
+                name
+            } else {
+                format!("CONFIG_{}", name)
+            };
+            match value {
+                serde_json::Value::Bool(false) | serde_json::Value::Null => (),
+                serde_json::Value::Bool(true) => config.define(&name, "1"),
+                serde_json::Value::String(s) => config.define(&name, &s),
+                value => config.define(&name, &value.to_string()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Reads the options listed in `CMAKE_CONFIG_NAMES` and `CMAKE_CONFIG_CHOICES`
+    /// from a CMakeCache.txt
+    pub fn from_cmake_cache(text: &str) -> Result<KernelConfig, ConfigError> {
+        let mut config = KernelConfig::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=').ok_or(ConfigError::Syntax(i + 1))?;
+            let (name, typ) = name.split_once(':').unwrap_or((name, "STRING"));
+
+            for (_, macro_name) in CMAKE_CONFIG_NAMES.iter().filter(|(var, _)| *var == name) {
+                if typ == "BOOL" {
+                    if cmake_bool(value) {
+                        config.define(macro_name, "1");
+                    }
+                } else {
+                    config.define(macro_name, value);
+                }
+            }
+            for (_, _, macro_name) in CMAKE_CONFIG_CHOICES
+                .iter()
+                .filter(|(var, choice, _)| *var == name && *choice == value)
+            {
+                config.define(macro_name, "1");
+            }
+        }
+        Ok(config)
+    }
+
+    /// The value of `name` in a `#if`, where undefined macros are `0`.
+    pub fn value(&self, name: &str) -> Result<i64, ConfigError> {
+        match self.get(name) {
+            None => Ok(0),
+            Some(value) => {
+                let text = value.trim_end_matches(['u', 'U', 'l', 'L']);
+                let parsed = if let Some(hex) =
+                    text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+                {
+                    i64::from_str_radix(hex, 16)
+                } else {
+                    text.parse()
+                };
+                parsed.map_err(|_| ConfigError::NotAnInteger(name.to_string(), value.to_string()))
+            }
+        }
+    }
+
+    fn integer(&self, condition: &Condition) -> Result<i64, ConfigError> {
+        Ok(match condition {
+            Condition::Defined(name) => self.is_defined(name) as i64,
+            Condition::Ident(name) => self.value(name)?,
+            Condition::Int(n) => *n,
+            Condition::Not(c) => (self.integer(c)? == 0) as i64,
+            Condition::And(cs) => {
+                for c in cs {
+                    if self.integer(c)? == 0 {
+                        return Ok(0);
+                    }
+                }
+                1
+            }
+            Condition::Or(cs) => {
+                for c in cs {
+                    if self.integer(c)? != 0 {
+                        return Ok(1);
+                    }
+                }
+                0
+            }
+            Condition::Compare(lhs, op, rhs) => {
+                op.apply(self.integer(lhs)?, self.integer(rhs)?) as i64
+            }
+        })
+    }
+
+    /// Evaluates `condition` the way `#if` would with this configuration.
+    pub fn evaluate(&self, condition: &Condition) -> Result<bool, ConfigError> {
+        Ok(self.integer(condition)? != 0)
+    }
+
+    /// Parses and evaluates an optional condition, where no condition always holds.
+    pub fn holds(&self, condition: Option<&str>) -> Result<bool, ConfigError> {
+        match condition {
+            None => Ok(true),
+            Some(condition) => self.evaluate(&condition.parse()?),
+        }
+    }
+}
+
+fn build_dir_file(dir: &Path) -> Result<PathBuf, ConfigError> {
+    BUILD_DIR_CANDIDATES
+        .iter()
+        .map(|candidate| dir.join(candidate))
+        .find(|path| path.is_file())
+        .ok_or_else(|| ConfigError::NotFound(dir.to_path_buf()))
+}
+
+fn cmake_bool(value: &str) -> bool {
+    matches!(
+        value.to_uppercase().as_str(),
+        "ON" | "1" | "TRUE" | "YES" | "Y"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invocations::{Api, ApiElement};
+    use crate::syscalls::Syscalls;
+    use crate::test_support::*;
+
+    const GEN_CONFIG_H: &str = r#"
+#pragma once
+
+#define CONFIG_ARCH_AARCH64  1
+/* disabled: CONFIG_ARCH_AARCH32 */
+#define CONFIG_KERNEL_MCS 1
+#define CONFIG_MAX_NUM_NODES 4
+#define CONFIG_ARM_PLAT imx8mq-evk
+"#;
+
+    #[test]
+    fn formats() -> Result<(), TestError> {
+        let h = KernelConfig::from_gen_config_h(GEN_CONFIG_H)?;
+        let json = KernelConfig::from_gen_config_json(
+            r#"{"ARCH_AARCH64": true, "ARCH_AARCH32": false, "KERNEL_MCS": true,
+                "MAX_NUM_NODES": "4", "ARM_PLAT": "imx8mq-evk"}"#,
+        )?;
+        let cmake = KernelConfig::from_cmake_cache(
+            r#"
+# This is the CMakeCache file.
+//Enable MCS
+KernelIsMCS:BOOL=ON
+KernelMaxNumNodes:STRING=4
+KernelPrinting:BOOL=OFF
+KernelSel4Arch:STRING=aarch64
+"#,
+        )?;
+        assert_eq!(h.get("CONFIG_ARM_PLAT"), Some("imx8mq-evk"));
+        assert_eq!(h, json);
+        for config in [h, json, cmake] {
+            assert!(config.is_defined("CONFIG_ARCH_AARCH64"));
+            assert!(!config.is_defined("CONFIG_ARCH_AARCH32"));
+            assert!(!config.is_defined("CONFIG_PRINTING"));
+            assert_eq!(config.value("CONFIG_MAX_NUM_NODES")?, 4);
+            assert_eq!(config.value("CONFIG_KERNEL_MCS")?, 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn resolve() -> Result<(), TestError> {
+        let dir = std::env::temp_dir().join(format!("sel4_xml_types-{}", std::process::id()));
+        let gen_config = dir.join("gen_config/kernel/gen_config.h");
+        fs::create_dir_all(gen_config.parent().unwrap_or(&dir))?;
+        let empty = KernelConfig::resolve(&dir);
+        fs::write(&gen_config, GEN_CONFIG_H)?;
+        let resolved = KernelConfig::resolve(&dir);
+        let config = KernelConfig::load(&dir);
+        fs::remove_dir_all(&dir)?;
+
+        assert!(matches!(empty, Err(ConfigError::NotFound(_))));
+        assert_eq!(resolved?, gen_config);
+        assert!(config?.is_defined("CONFIG_KERNEL_MCS"));
+        assert_eq!(
+            KernelConfig::resolve("build/CMakeCache.txt")?,
+            Path::new("build/CMakeCache.txt")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate() -> Result<(), TestError> {
+        let config = KernelConfig::from_gen_config_h(GEN_CONFIG_H)?;
+        assert!(config.holds(None)?);
+        assert!(config.holds(Some("defined(CONFIG_KERNEL_MCS)"))?);
+        assert!(!config.holds(Some("!defined CONFIG_KERNEL_MCS"))?);
+        assert!(config.holds(Some("CONFIG_MAX_NUM_NODES > 1 && CONFIG_KERNEL_MCS"))?);
+        assert!(!config.holds(Some("CONFIG_PRINTING || CONFIG_MAX_NUM_NODES == 1"))?);
+        assert!(config.holds(Some("CONFIG_ARM_PLAT")).is_err());
+
+        let mut config = KernelConfig::new();
+        config.define("CONFIG_PADDR_USER_DEVICE_TOP", "0x8000UL");
+        config.define("CONFIG_KERNEL_STACK_BITS", "0XC");
+        assert_eq!(config.value("CONFIG_PADDR_USER_DEVICE_TOP")?, 0x8000);
+        assert_eq!(config.value("CONFIG_KERNEL_STACK_BITS")?, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn configure() -> Result<(), TestError> {
+        let mut config = KernelConfig::new();
+        config.define("CONFIG_MAX_NUM_NODES", "2");
+
+        let xml = r#"
+<api name="ObjectApi">
+    <interface name="seL4_TCB">
+        <method id="TCBConfigure" name="Configure"/>
+        <method id="TCBSetTimeoutEndpoint" name="SetTimeoutEndpoint" condition="defined(CONFIG_KERNEL_MCS)"/>
+        <method id="TCBSetAffinity" name="SetAffinity" condition="(!defined CONFIG_KERNEL_MCS) &amp;&amp; CONFIG_MAX_NUM_NODES > 1"/>
+    </interface>
+</api>"#;
+        let api = Api::try_from(roxmltree::Document::parse(xml)?)?;
+        let configured = api.configure(&config, 1)?;
+        let labels: Vec<_> = configured
+            .labels
+            .iter()
+            .map(|label| (label.id.as_str(), label.value))
+            .collect();
+        assert_eq!(labels, [("TCBConfigure", 1), ("TCBSetAffinity", 2)]);
+        match &configured.api.children[0] {
+            ApiElement::Interface { methods, .. } => {
+                assert_eq!(methods.len(), 2);
+                assert!(methods.iter().all(|method| method.condition.is_none()));
+            }
+            _ => panic!("expected an interface"),
+        }
+
+        let syscalls = Syscalls::parse(
+            Path::new("syscall.xml"),
+            r#"
+<syscalls>
+    <api-master><config><syscall name="Call"/></config></api-master>
+    <api-mcs><config><syscall name="Call"/></config></api-mcs>
+    <debug>
+        <config condition="defined CONFIG_PRINTING"><syscall name="DebugPutChar"/></config>
+        <config condition="CONFIG_MAX_NUM_NODES > 1"><syscall name="DebugSnapshot"/></config>
+    </debug>
+</syscalls>"#,
+        )?;
+        let syscalls = syscalls.configure(&config)?;
+        // DebugPutChar is disabled, but keeps its number.
+        let numbered = syscalls.numbered(false, true);
+        let numbers: Vec<_> = numbered
+            .iter()
+            .map(|syscall| (syscall.name.as_str(), syscall.number))
+            .collect();
+        assert_eq!(numbers, [("Call", -1), ("DebugSnapshot", -3)]);
+        assert!(numbered.iter().all(|syscall| syscall.condition.is_none()));
+        assert_eq!(syscalls.syscall_min(false), -1);
+        Ok(())
+    }
+}
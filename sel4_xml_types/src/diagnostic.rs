@@ -0,0 +1,510 @@
+use crate::error::Sel4XmlError;
+use crate::invocation_helper::start_tag_end;
+use crate::validate::{self, ValidationError};
+use crate::xref::{DanglingRef, LabelIndex, RefKind, Xref};
+use codespan_reporting::diagnostic::{Diagnostic as Report, Label};
+use codespan_reporting::files::SimpleFile;
+use codespan_reporting::term::{self, termcolor};
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use std::path::Path;
+
+//
+// Compiler style reports of errors in the xml files, the offending source
+// is shown with the bad element or attribute underlined.
+//
+// The errors of the parsers say what went wrong and roughly where,
+// the source is parsed again with roxmltree to find the exact element
+// and to suggest names for misspelled ones.
+//
+
+/// The elements of sel4_idl.dtd, suggested for misspelled elements.
+pub const IDL_ELEMENTS: &[&str] = &[
+    "api",
+    "struct",
+    "member",
+    "interface",
+    "method",
+    "brief",
+    "description",
+    "return",
+    "errorenumdesc",
+    "cap_param",
+    "param",
+    "error",
+    "docref",
+    "texttt",
+    "shortref",
+    "autoref",
+    "obj",
+];
+
+/// The elements of syscall.xml.
+pub const SYSCALL_ELEMENTS: &[&str] = &[
+    "syscalls",
+    "api-master",
+    "api-mcs",
+    "debug",
+    "config",
+    "syscall",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// The bytes of the source to underline.
+    pub span: Option<Range<usize>>,
+    /// Shown under the underlined source.
+    pub label: String,
+    /// Such as the element path and `help: did you mean ...`
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            span: None,
+            label: String::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_span(self, span: Range<usize>, label: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            span: Some(span),
+            label: label.into(),
+            ..self
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// For xml which isn't well formed.
+    pub fn from_xml_error(err: &roxmltree::Error, source: &str) -> Diagnostic {
+        let pos = err.pos();
+        let diagnostic = Diagnostic::new(err.to_string());
+        match offset(source, pos.row, pos.col) {
+            Some(start) => {
+                let end = source[start..]
+                    .chars()
+                    .next()
+                    .map_or(start, |c| start + c.len_utf8());
+                diagnostic.with_span(start..end, "")
+            }
+            None => diagnostic,
+        }
+    }
+
+    /// For errors parsing `source` into an `invocations::Api` or `syscalls::Syscalls`.
+    pub fn from_sel4_xml_error(err: &Sel4XmlError, source: &str) -> Diagnostic {
+        let doc = roxmltree::Document::parse(source);
+        match (err, &doc) {
+            (Sel4XmlError::Roxmltree(err) | Sel4XmlError::Xml(_, err), _) => {
+                Diagnostic::from_xml_error(err, source)
+            }
+            (Sel4XmlError::Invalid(err), _) => Diagnostic::from_validation_error(err),
+            (_, Err(err)) => Diagnostic::from_xml_error(err, source),
+            (Sel4XmlError::Attribute(attr, location), Ok(doc)) => {
+                let diagnostic = Diagnostic::new(format!("attribute `{}` not found", attr))
+                    .with_note(format!("in {}", location.path));
+                let node = doc
+                    .descendants()
+                    .find(|node| node.is_element() && node.range().start == location.span.start);
+                let misspelling = node.and_then(|node| {
+                    node.attributes()
+                        .iter()
+                        .find(|found| did_you_mean(found.name(), &[attr.as_str()]).is_some())
+                });
+                match misspelling {
+                    Some(found) => diagnostic
+                        .with_span(found.range(), format!("expected `{}`", attr))
+                        .with_note(format!("help: did you mean `{}`?", attr)),
+                    None => {
+                        diagnostic.with_span(location.span.clone(), format!("missing `{}`", attr))
+                    }
+                }
+            }
+            (Sel4XmlError::Element(name, location), Ok(doc)) => {
+                let diagnostic = Diagnostic::new(format!("element `{}` not found", name))
+                    .with_note(format!("in {}", location.path));
+                let node = doc
+                    .descendants()
+                    .find(|node| node.is_element() && node.range().start == location.span.start);
+                let misspelling = node.and_then(|node| {
+                    node.children().find(|child| {
+                        child.is_element()
+                            && did_you_mean(child.tag_name().name(), &[name.as_str()]).is_some()
+                    })
+                });
+                match misspelling {
+                    Some(found) => diagnostic
+                        .with_span(start_tag(found), format!("expected `{}`", name))
+                        .with_note(format!("help: did you mean `{}`?", name)),
+                    None => {
+                        diagnostic.with_span(location.span.clone(), format!("missing `{}`", name))
+                    }
+                }
+            }
+            (Sel4XmlError::UnsupportedNodeType(name, location), Ok(doc)) => {
+                let message = if name.is_empty() {
+                    "unexpected text".to_string()
+                } else {
+                    format!("unexpected element `{}`", name)
+                };
+                let diagnostic = Diagnostic::new(message)
+                    .with_span(location.span.clone(), "not allowed here")
+                    .with_note(format!("in {}", location.path));
+                let elements = match doc.root_element().tag_name().name() {
+                    "syscalls" => SYSCALL_ELEMENTS,
+                    _ => IDL_ELEMENTS,
+                };
+                match did_you_mean(name, elements) {
+                    Some(element) => {
+                        diagnostic.with_note(format!("help: did you mean `{}`?", element))
+                    }
+                    None => diagnostic,
+                }
+            }
+        }
+    }
+
+    /// For xml which doesn't follow sel4_idl.dtd.
+    pub fn from_validation_error(err: &ValidationError) -> Diagnostic {
+        let location = err.location();
+        let (message, label, suggestion) = match err {
+            ValidationError::UnknownElement(name, _, parent) => (
+                format!("element `{}` is not allowed in `{}`", name, parent),
+                "not allowed here".to_string(),
+                did_you_mean(name, IDL_ELEMENTS),
+            ),
+            ValidationError::Order(name, _, expected) => (
+                format!("element `{}` is out of order", name),
+                format!("expected {}", expected),
+                None,
+            ),
+            ValidationError::MissingAttribute(attr, _) => (
+                format!("required attribute `{}` is missing", attr),
+                format!("missing `{}`", attr),
+                None,
+            ),
+            ValidationError::UnknownAttribute(attr, _, element) => (
+                format!("attribute `{}` is not allowed on `{}`", attr, element),
+                "unknown attribute".to_string(),
+                did_you_mean(attr, &validate::attributes(element)),
+            ),
+            ValidationError::Value(attr, _, values) => (
+                format!("invalid value for `{}`", attr),
+                format!("expected {}", values),
+                None,
+            ),
+            ValidationError::Text(_, element) => (
+                format!("text is not allowed in `{}`", element),
+                "not allowed here".to_string(),
+                None,
+            ),
+        };
+        let diagnostic = Diagnostic::new(message)
+            .with_span(location.span.clone(), label)
+            .with_note(format!("in {}", location.path));
+        match suggestion {
+            Some(name) => diagnostic.with_note(format!("help: did you mean `{}`?", name)),
+            None => diagnostic,
+        }
+    }
+
+    /// For references to labels missing from `labels`.
+    pub fn from_dangling_ref(err: &DanglingRef, source: &str, labels: &LabelIndex) -> Diagnostic {
+        let xref = &err.0;
+        let label = xref.label();
+        let diagnostic = Diagnostic::new(format!("unknown label `{}`", label))
+            .with_note(format!("in the documentation of {}", xref.method));
+        let diagnostic = match offset(source, xref.position.line, xref.position.column) {
+            Some(start) => diagnostic.with_span(
+                reference_tag(xref, source, start),
+                format!("{} to a label not in the manual", xref.kind),
+            ),
+            None => diagnostic,
+        };
+        let candidates: Vec<&str> = labels.iter().collect();
+        match did_you_mean(&label, &candidates) {
+            Some(label) => diagnostic.with_note(format!("help: did you mean `{}`?", label)),
+            None => diagnostic,
+        }
+    }
+
+    /// Writes the diagnostic for `source`, read from `file`.
+    pub fn write(
+        &self,
+        writer: &mut dyn termcolor::WriteColor,
+        file: &Path,
+        source: &str,
+    ) -> io::Result<()> {
+        let file = SimpleFile::new(file.display().to_string(), source);
+        let mut report = Report::error()
+            .with_message(&self.message)
+            .with_notes(self.notes.clone());
+        if let Some(span) = &self.span {
+            report = report.with_labels(vec![
+                Label::primary((), span.clone()).with_message(&self.label)
+            ]);
+        }
+        term::emit(writer, &term::Config::default(), &file, &report).map_err(io::Error::other)
+    }
+
+    /// Writes the diagnostic to stderr, in colour if it is a terminal.
+    pub fn emit(&self, file: &Path, source: &str) -> io::Result<()> {
+        let color = if io::stderr().is_terminal() {
+            termcolor::ColorChoice::Auto
+        } else {
+            termcolor::ColorChoice::Never
+        };
+        let stderr = termcolor::StandardStream::stderr(color);
+        let mut stderr = stderr.lock();
+        self.write(&mut stderr, file, source)
+    }
+}
+
+/// The candidate `name` is most likely a misspelling of, if any.
+pub fn did_you_mean<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .filter(|candidate| **candidate != name)
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), *candidate))
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| candidate)
+}
+
+// The byte offset of a roxmltree line and column, which counts characters.
+// Elements which weren't parsed are at line 0, and have none.
+fn offset(source: &str, line: u32, column: u32) -> Option<usize> {
+    if line == 0 || column == 0 {
+        return None;
+    }
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line as usize - 1)
+        .map(str::len)
+        .sum();
+    Some(
+        source[line_start..]
+            .char_indices()
+            .nth(column as usize - 1)
+            .map_or(source.len(), |(i, _)| line_start + i),
+    )
+}
+
+// The start tag of `xref`, found within the element at `start` as references
+// in a `<docref>` have the position of the docref.
+fn reference_tag(xref: &Xref, source: &str, start: usize) -> Range<usize> {
+    let attribute = match xref.kind {
+        RefKind::AutoRef => "label",
+        RefKind::ShortRef => "sec",
+    };
+    let doc = roxmltree::Document::parse(source);
+    let reference = doc.as_ref().ok().and_then(|doc| {
+        doc.descendants()
+            .find(|node| node.is_element() && node.range().start == start)?
+            .descendants()
+            .find(|node| {
+                node.tag_name().name() == xref.kind.to_string()
+                    && node.attribute(attribute) == Some(xref.target.as_str())
+            })
+    });
+    match reference {
+        Some(node) => start_tag(node),
+        None => {
+            let end = source[start..]
+                .find('>')
+                .map_or(start, |end| start + end + 1);
+            start..end
+        }
+    }
+}
+
+fn start_tag(node: roxmltree::Node<'_, '_>) -> Range<usize> {
+    let range = node.range();
+    match start_tag_end(node) {
+        Some(end) => range.start..end,
+        None => range,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Location, Position};
+    use crate::invocations::Api;
+    use crate::syscalls::Syscalls;
+    use crate::test_support::*;
+
+    const XML: &str = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSuspend" nmae="Suspend">
+            <description>Suspend <textt text="tcb"/>.</description>
+        </method>
+    </interface>
+</api>"#;
+
+    fn render(diagnostic: &Diagnostic, source: &str) -> Result<String, TestError> {
+        let mut buffer = termcolor::NoColor::new(Vec::new());
+        diagnostic.write(&mut buffer, Path::new("sel4.xml"), source)?;
+        Ok(String::from_utf8(buffer.into_inner()).expect("utf8 diagnostic"))
+    }
+
+    #[test]
+    fn misspelled_attribute() -> Result<(), TestError> {
+        let err = Api::parse(Path::new("sel4.xml"), XML).unwrap_err();
+        let diagnostic = Diagnostic::from_sel4_xml_error(&err, XML);
+        assert_eq!(diagnostic.message, "attribute `name` not found");
+        assert_eq!(&XML[diagnostic.span.clone().unwrap()], "nmae=\"Suspend\"");
+
+        let rendered = render(&diagnostic, XML)?;
+        assert!(rendered.starts_with("error: attribute `name` not found"));
+        assert!(rendered.contains("sel4.xml:3:33"));
+        assert!(rendered.contains("^^^^^^^^^^^^^^ expected `name`"));
+        assert!(rendered.contains("help: did you mean `name`?"));
+        Ok(())
+    }
+
+    #[test]
+    fn misspelled_element() -> Result<(), TestError> {
+        let xml = XML.replace("nmae", "name");
+        let err = Api::parse(Path::new("sel4.xml"), &xml).unwrap_err();
+        let diagnostic = Diagnostic::from_sel4_xml_error(&err, &xml);
+        assert_eq!(diagnostic.message, "unexpected element `textt`");
+        assert_eq!(
+            diagnostic.notes,
+            [
+                "in interface[@name=seL4_TCB]/method[@id=TCBSuspend]/description[1]/textt[1]",
+                "help: did you mean `texttt`?"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn validation() -> Result<(), TestError> {
+        let xml = XML.replace(
+            "<description>",
+            "<param dir=\"in\" name=\"a\" typ=\"seL4_Word\"/><description>",
+        );
+        let (_, errors) = Api::parse_validated(Path::new("sel4.xml"), &xml)?;
+        let diagnostics: Vec<Diagnostic> = errors
+            .iter()
+            .map(|err| Diagnostic::from_sel4_xml_error(err, &xml))
+            .collect();
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "attribute `nmae` is not allowed on `method`",
+                "required attribute `name` is missing",
+                "attribute `typ` is not allowed on `param`",
+                "required attribute `type` is missing",
+                "element `description` is out of order",
+                "element `textt` is not allowed in `description`",
+            ]
+        );
+        assert_eq!(diagnostics[0].notes[1], "help: did you mean `name`?");
+        assert_eq!(diagnostics[2].notes[1], "help: did you mean `type`?");
+        assert_eq!(diagnostics[4].label, "expected one of `param`, `error`");
+        assert_eq!(diagnostics[5].notes[1], "help: did you mean `texttt`?");
+        Ok(())
+    }
+
+    #[test]
+    fn dangling_ref() -> Result<(), TestError> {
+        let xml = XML
+            .replace("nmae", "name")
+            .replace("<textt text=\"tcb\"/>", "<autoref label=\"sec:thread\"/>");
+        let api = Api::parse(Path::new("sel4.xml"), &xml)?;
+        let labels = LabelIndex::parse("sec:threads\nsec:faults");
+        let dangling = labels.resolve(&api);
+        let diagnostic = Diagnostic::from_dangling_ref(&dangling[0], &xml, &labels);
+        assert_eq!(diagnostic.message, "unknown label `sec:thread`");
+        assert_eq!(
+            &xml[diagnostic.span.clone().unwrap()],
+            "<autoref label=\"sec:thread\"/>"
+        );
+        assert_eq!(
+            diagnostic.notes,
+            [
+                "in the documentation of TCBSuspend",
+                "help: did you mean `sec:threads`?"
+            ]
+        );
+
+        // Those within a docref are found in it.
+        let xml = xml.replace(
+            "<autoref label=\"sec:thread\"/>",
+            "<docref>see <shortref sec=\"threads\"/> and <autoref label=\"sec:thread\"/></docref>",
+        );
+        let api = Api::parse(Path::new("sel4.xml"), &xml)?;
+        let dangling = labels.resolve(&api);
+        assert_eq!(dangling.len(), 1);
+        let diagnostic = Diagnostic::from_dangling_ref(&dangling[0], &xml, &labels);
+        assert_eq!(
+            &xml[diagnostic.span.clone().unwrap()],
+            "<autoref label=\"sec:thread\"/>"
+        );
+
+        // References built by hand have no position in the source.
+        let unparsed = DanglingRef(Xref {
+            position: Position::default(),
+            ..dangling[0].0.clone()
+        });
+        let diagnostic = Diagnostic::from_dangling_ref(&unparsed, &xml, &labels);
+        assert_eq!(diagnostic.message, "unknown label `sec:thread`");
+        assert!(diagnostic.span.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn syscalls() -> Result<(), TestError> {
+        let xml = r#"<syscalls>
+    <api-master><config><syscall name="Call"/></config></api-master>
+    <api-mcs><config><syscall name="Call"/></config></api-mcs>
+    <debg><config><syscall name="DebugPutChar"/></config></debg>
+</syscalls>"#;
+        let err = Syscalls::parse(Path::new("syscall.xml"), xml).unwrap_err();
+        let diagnostic = Diagnostic::from_sel4_xml_error(&err, xml);
+        assert_eq!(diagnostic.message, "unexpected element `debg`");
+        assert_eq!(&xml[diagnostic.span.clone().unwrap()], "<debg>");
+        assert_eq!(diagnostic.notes[1], "help: did you mean `debug`?");
+
+        let xml = xml
+            .replace("<debg>", "<!-- <debg>")
+            .replace("</debg>", "</debg> -->");
+        let err = Syscalls::parse(Path::new("syscall.xml"), &xml).unwrap_err();
+        let diagnostic = Diagnostic::from_sel4_xml_error(&err, &xml);
+        assert_eq!(diagnostic.message, "element `debug` not found");
+        assert_eq!(diagnostic.label, "missing `debug`");
+
+        // The whole start tag of a misspelling is underlined, `>` in its attributes included.
+        let xml = r#"<syscalls>
+    <debug><confg condition="CONFIG_MAX_NUM_NODES > 1"/></debug>
+</syscalls>"#;
+        let start = xml.find("<debug>").unwrap_or_default();
+        let err = Sel4XmlError::Element(
+            "config".to_string(),
+            Location {
+                file: None,
+                position: Position { line: 2, column: 5 },
+                span: start..start + "<debug>".len(),
+                path: "debug".to_string(),
+            },
+        );
+        let diagnostic = Diagnostic::from_sel4_xml_error(&err, xml);
+        assert_eq!(
+            &xml[diagnostic.span.clone().unwrap()],
+            r#"<confg condition="CONFIG_MAX_NUM_NODES > 1"/>"#
+        );
+        assert_eq!(diagnostic.label, "expected `config`");
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::validate::ValidationError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+//
+// The errors of reading both syscall.xml and the IDL files.
+//
+// Both are parsed with roxmltree, so apart from xml which isn't well
+// formed every error has the `Location` of the element it was found in.
+//
+
+#[derive(Error, Debug)]
+pub enum Sel4XmlError {
+    #[error("roxmltree error {0}")]
+    Roxmltree(#[from] roxmltree::Error),
+    #[error("{}: {1}", .0.display())]
+    Xml(PathBuf, roxmltree::Error),
+    #[error("{1}: attribute '{0}' not found")]
+    Attribute(String, Location),
+    #[error("{1}: element '{0}' not found")]
+    Element(String, Location),
+    #[error("{1}: cannot convert element from unknown element '{0}'")]
+    UnsupportedNodeType(String, Location),
+    #[error("{0}")]
+    Invalid(Box<ValidationError>),
+}
+
+impl From<ValidationError> for Sel4XmlError {
+    fn from(err: ValidationError) -> Sel4XmlError {
+        Sel4XmlError::Invalid(Box::new(err))
+    }
+}
+
+impl Sel4XmlError {
+    /// Where in the xml the error is, if the xml was well formed.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Sel4XmlError::Attribute(_, location)
+            | Sel4XmlError::Element(_, location)
+            | Sel4XmlError::UnsupportedNodeType(_, location) => Some(location),
+            Sel4XmlError::Invalid(err) => Some(err.location()),
+            Sel4XmlError::Roxmltree(_) | Sel4XmlError::Xml(..) => None,
+        }
+    }
+
+    /// Records the file the xml was read from.
+    pub fn in_file(self, file: &Path) -> Sel4XmlError {
+        match self {
+            Sel4XmlError::Roxmltree(err) => Sel4XmlError::Xml(file.to_path_buf(), err),
+            Sel4XmlError::Attribute(attr, location) => {
+                Sel4XmlError::Attribute(attr, location.in_file(file))
+            }
+            Sel4XmlError::Element(name, location) => {
+                Sel4XmlError::Element(name, location.in_file(file))
+            }
+            Sel4XmlError::UnsupportedNodeType(name, location) => {
+                Sel4XmlError::UnsupportedNodeType(name, location.in_file(file))
+            }
+            err => err,
+        }
+    }
+}
+
+/// A line and column in the xml, both starting from 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Where an error was found in an xml file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    /// `None` unless the xml was parsed with `Api::parse` or `Syscalls::parse`
+    pub file: Option<PathBuf>,
+    pub position: Position,
+    /// The bytes of the offending start tag or attribute.
+    pub span: Range<usize>,
+    /// e.g. `interface[@name=seL4_TCB]/method[@id=TCBConfigure]/param[2]`
+    pub path: String,
+}
+
+impl Location {
+    pub(crate) fn in_file(self, file: &Path) -> Location {
+        Location {
+            file: Some(file.to_path_buf()),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{} {}", self.position, self.path)
+    }
+}
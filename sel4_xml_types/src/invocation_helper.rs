@@ -1,70 +1,192 @@
+use crate::borrowed::{DocLeaf, Return};
 use crate::invocations::*;
-pub(crate) trait AttributeExt {
+pub(crate) trait AttributeExt<'a> {
     type Error;
 
-    fn opt_attr(self, attr: &'_ str) -> Option<String>;
-    fn req_attr(self, attr: &'_ str) -> Result<String, Self::Error>;
+    fn opt_attr(self, attr: &'_ str) -> Option<&'a str>;
+    fn req_attr(self, attr: &'_ str) -> Result<&'a str, Self::Error>;
 }
 
-impl AttributeExt for roxmltree::Node<'_, '_> {
-    type Error = InvocationGenError;
-    fn opt_attr(self, attr: &'_ str) -> Option<String> {
-        self.attribute(attr).map(str::to_string)
+impl<'a> AttributeExt<'a> for roxmltree::Node<'a, '_> {
+    type Error = Sel4XmlError;
+    fn opt_attr(self, attr: &'_ str) -> Option<&'a str> {
+        self.attribute(attr)
     }
 
-    fn req_attr(self, attr: &'_ str) -> Result<String, InvocationGenError> {
+    fn req_attr(self, attr: &'_ str) -> Result<&'a str, Sel4XmlError> {
         self.attribute(attr)
-            .map(str::to_string)
-            .ok_or_else(|| InvocationGenError::Attribute(attr.to_string(), file!(), line!()))
+            .ok_or_else(|| Sel4XmlError::Attribute(attr.to_string(), location(self)))
+    }
+}
+
+pub(crate) fn position(node: roxmltree::Node<'_, '_>) -> Position {
+    text_position(node, node.range().start)
+}
+
+/// The position of the attribute, or of its element if it isn't there.
+pub(crate) fn attribute_position(node: roxmltree::Node<'_, '_>, attr: &str) -> Position {
+    match node.attribute_node(attr) {
+        Some(attr) => text_position(node, attr.range().start),
+        None => position(node),
     }
 }
 
+fn text_position(node: roxmltree::Node<'_, '_>, pos: usize) -> Position {
+    let pos = node.document().text_pos_at(pos);
+    Position {
+        line: pos.row,
+        column: pos.col,
+    }
+}
+
+// The end of the start tag of an element. Attribute values may contain
+// `>`, so it is looked for after the last attribute.
+pub(crate) fn start_tag_end(node: roxmltree::Node<'_, '_>) -> Option<usize> {
+    let range = node.range();
+    let from = node
+        .attributes()
+        .last()
+        .map_or(range.start, |attr| attr.range().end);
+    node.document().input_text()[from..range.end]
+        .find('>')
+        .map(|end| from + end + 1)
+}
+
+pub(crate) fn location(node: roxmltree::Node<'_, '_>) -> Location {
+    // Just the start tag, the whole element may span many lines.
+    let range = node.range();
+    let end = if node.is_element() {
+        start_tag_end(node).unwrap_or(range.end)
+    } else {
+        range.end
+    };
+    Location {
+        file: None,
+        position: position(node),
+        span: range.start..end,
+        path: element_path(node),
+    }
+}
+
+// The attributes, the end of the start tag and the gaps between child
+// elements as written, for writing the element back the same way.
+pub(crate) fn formatting(node: roxmltree::Node<'_, '_>) -> Formatting {
+    let text = node.document().input_text();
+    let range = node.range();
+    let mut end = range.start + "<".len() + node.tag_name().name().len();
+    let mut attributes = Vec::new();
+    for attr in node.attributes() {
+        attributes.push(RawAttribute {
+            name: attr.name().to_string(),
+            before: text[end..attr.range().start].to_string(),
+            raw: text[attr.range()].to_string(),
+            value: attr.value().to_string(),
+        });
+        end = attr.range().end;
+    }
+    let tag_end_len = text[end..range.end].find('>').map_or(0, |gt| gt + 1);
+    let tag_end = text[end..end + tag_end_len].to_string();
+    end += tag_end_len;
+
+    let mut gaps = Vec::new();
+    for child in node.children().filter(|child| child.is_element()) {
+        let name = child.attribute("id").or_else(|| child.attribute("name"));
+        gaps.push((
+            gap_key(child.tag_name().name(), name),
+            text[end..child.range().start].to_string(),
+        ));
+        end = child.range().end;
+    }
+    let end_gap = match text[end..range.end].rfind("</") {
+        Some(end_tag) => text[end..end + end_tag].to_string(),
+        None => String::new(),
+    };
+
+    Formatting {
+        attributes,
+        tag_end,
+        gaps,
+        end_gap,
+        ..Formatting::default()
+    }
+}
+
+pub(crate) fn unsupported_node(node: roxmltree::Node<'_, '_>) -> Sel4XmlError {
+    Sel4XmlError::UnsupportedNodeType(node.tag_name().name().to_string(), location(node))
+}
+
+// e.g. `interface[@name=seL4_TCB]/method[@id=TCBConfigure]/param[2]`,
+// the root `api` element is left out unless the node is the root.
+pub(crate) fn element_path(node: roxmltree::Node<'_, '_>) -> String {
+    let root = node.document().root_element();
+    let mut segments: Vec<String> = node
+        .ancestors()
+        .filter(|node| node.is_element() && *node != root)
+        .map(path_segment)
+        .collect();
+    if segments.is_empty() {
+        return root.tag_name().name().to_string();
+    }
+    segments.reverse();
+    segments.join("/")
+}
+
+fn path_segment(node: roxmltree::Node<'_, '_>) -> String {
+    let tag = node.tag_name().name();
+    match (tag, node.attribute("id"), node.attribute("name")) {
+        ("method", Some(id), _) => format!("{}[@id={}]", tag, id),
+        ("interface" | "struct", _, Some(name)) => format!("{}[@name={}]", tag, name),
+        _ => {
+            let index = node
+                .prev_siblings()
+                .filter(|sibling| sibling.is_element() && sibling.tag_name().name() == tag)
+                .count();
+            format!("{}[{}]", tag, index)
+        }
+    }
+}
+
+// Skips whitespace and comments, up to the next node of interest.
 pub(crate) fn skip_irrelevant<'a, 'b>(
-    node: Option<roxmltree::Node<'a, 'b>>,
+    mut node: Option<roxmltree::Node<'a, 'b>>,
 ) -> Option<roxmltree::Node<'a, 'b>> {
-    match node {
-        None => None,
-        Some(node)
-            if (node.node_type() == roxmltree::NodeType::Text
-                && node.text().unwrap().trim().is_empty())
-                || node.node_type() == roxmltree::NodeType::Comment =>
-        {
-            node.next_sibling()
-        }
-        node => node,
+    while let Some(irrelevant) = node.filter(|node| is_whitespace(*node) || node.is_comment()) {
+        node = irrelevant.next_sibling();
     }
+    node
 }
 
-pub(crate) fn take_return_value_from_element<'a, 'b>(
-    node: roxmltree::Node<'a, 'b>,
-    v: &mut Vec<Return>,
-) -> Result<Option<roxmltree::Node<'a, 'b>>, InvocationGenError> {
+pub(crate) fn take_return_value_from_element<'a>(
+    node: roxmltree::Node<'a, 'a>,
+    v: &mut Vec<Return<'a>>,
+    recovery: &mut Recovery,
+) -> Result<Option<roxmltree::Node<'a, 'a>>, Sel4XmlError> {
     match node.node_type() {
-        roxmltree::NodeType::Element => match node.tag_name().name().to_lowercase() {
-            s if s == "return" => {
-                for child in node.children() {
-                    let child = Return::try_from(child)?;
+        roxmltree::NodeType::Element if node.tag_name().name().eq_ignore_ascii_case("return") => {
+            for child in node.children() {
+                if let Some(child) = recovery.skip(Return::try_from(child))? {
                     v.push(child);
                 }
-                Ok(node.next_sibling())
             }
-            _ => Ok(Some(node)),
-        },
+            Ok(node.next_sibling())
+        }
         _ => Ok(Some(node)),
     }
 }
 
-pub(crate) fn take_leaves_from_element<'a, 'b>(
-    node: roxmltree::Node<'a, 'b>,
+pub(crate) fn take_leaves_from_element<'a>(
+    node: roxmltree::Node<'a, 'a>,
     name: &'_ str,
-    v: &mut Vec<DocLeaf>,
-) -> Result<Option<roxmltree::Node<'a, 'b>>, InvocationGenError> {
+    v: &mut Vec<DocLeaf<'a>>,
+    recovery: &mut Recovery,
+) -> Result<Option<roxmltree::Node<'a, 'a>>, Sel4XmlError> {
     match node.node_type() {
         roxmltree::NodeType::Element => match node.tag_name().name().to_lowercase() {
             s if s == name => {
                 for child in node.children() {
-                    let child = DocLeaf::try_from(child)?;
-                    v.push(child);
+                    if let Some(child) = recovery.skip(DocLeaf::try_from(child))? {
+                        v.push(child);
+                    }
                 }
                 Ok(node.next_sibling())
             }
@@ -74,36 +196,44 @@ pub(crate) fn take_leaves_from_element<'a, 'b>(
     }
 }
 
-// It is kind of annoying but somehow I ended up with 2 different mechanisms
-// for ignoring whitespace elements..
-pub(crate) enum WhitespaceOr<'a, 'b, T> {
-    Whitespace(roxmltree::Node<'a, 'b>),
-    T(T),
+pub(crate) fn is_whitespace(node: roxmltree::Node<'_, '_>) -> bool {
+    node.is_text() && node.text().unwrap().trim().is_empty()
+}
+
+// Whether an error in a child element stops the parse, or is collected
+// while the child is left out of its parent.
+pub(crate) struct Recovery {
+    recover: bool,
+    pub(crate) errors: Vec<Sel4XmlError>,
 }
 
-impl<'a, 'b, T> TryFrom<roxmltree::Node<'a, 'b>> for WhitespaceOr<'a, 'b, T>
-where
-    T: TryFrom<roxmltree::Node<'a, 'b>, Error = InvocationGenError>,
-{
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, Self::Error> {
-        if node.is_text() && node.text().unwrap().trim() == "" {
-            Ok(WhitespaceOr::Whitespace(node))
-        } else {
-            Ok(WhitespaceOr::T(T::try_from(node)?))
+impl Recovery {
+    pub(crate) fn strict() -> Recovery {
+        Recovery {
+            recover: false,
+            errors: Vec::new(),
         }
     }
-}
 
-impl<'a, 'b, T> WhitespaceOr<'a, 'b, T>
-where
-    T: TryFrom<roxmltree::Node<'a, 'b>, Error = InvocationGenError>,
-{
-    #[allow(unused)]
-    pub(crate) fn ignore_whitespace(self: WhitespaceOr<'a, 'b, T>) -> Option<T> {
-        match self {
-            WhitespaceOr::T(child) => Some(child),
-            _ => None,
+    pub(crate) fn recovering() -> Recovery {
+        Recovery {
+            recover: true,
+            errors: Vec::new(),
+        }
+    }
+
+    /// `None` in place of a child which failed to parse, if recovering.
+    pub(crate) fn skip<T>(
+        &mut self,
+        result: Result<T, Sel4XmlError>,
+    ) -> Result<Option<T>, Sel4XmlError> {
+        match result {
+            Ok(child) => Ok(Some(child)),
+            Err(err) if self.recover => {
+                self.errors.push(err);
+                Ok(None)
+            }
+            Err(err) => Err(err),
         }
     }
 }
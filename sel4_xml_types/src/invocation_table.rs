@@ -0,0 +1,243 @@
+use crate::config::{ConfigError, KernelConfig};
+use crate::invocations::{Api, ApiElement};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+//
+// The numeric value of every invocation label, as the C compiler would
+// assign them from the three enums generated by gen_invocations:
+//
+//   enum invocation_label { InvalidInvocation, ..., nInvocationLabels };
+//   enum sel4_arch_invocation_label { X = nInvocationLabels, ..., nSeL4ArchInvocationLabels };
+//   enum arch_invocation_label { Y = nSeL4ArchInvocationLabels, ..., nArchInvocationLabels };
+//
+
+/// Which of the three label enums an invocation belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Layer {
+    /// sel4.xml, `invocation_label`
+    Generic,
+    /// sel4_arch_include/*/interfaces/sel4arch.xml, `sel4_arch_invocation_label`
+    Sel4Arch,
+    /// arch_include/*/interfaces/sel4arch.xml, `arch_invocation_label`
+    Arch,
+}
+
+impl Layer {
+    /// The name of the C enum the layer's labels are generated into.
+    pub fn enum_name(self) -> &'static str {
+        match self {
+            Layer::Generic => "invocation_label",
+            Layer::Sel4Arch => "sel4_arch_invocation_label",
+            Layer::Arch => "arch_invocation_label",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvocationLabel {
+    /// The method id e.g. `TCBConfigure`
+    pub id: String,
+    pub value: usize,
+    pub layer: Layer,
+    /// The interface the method belongs to e.g. `seL4_TCB`
+    pub interface: String,
+    /// The method name e.g. `Configure`
+    pub method: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvocationTable {
+    labels: Vec<InvocationLabel>,
+    by_name: HashMap<String, usize>,
+    n_invocation_labels: usize,
+    n_sel4_arch_invocation_labels: usize,
+    n_arch_invocation_labels: usize,
+}
+
+impl InvocationTable {
+    /// Numbers the methods of the three layers of a kernel api.
+    ///
+    /// Only the methods whose condition holds in `config` are numbered,
+    /// without a config that is every method that is unconditional or whose
+    /// condition holds when no configuration options are defined.
+    pub fn new(
+        generic: &Api,
+        sel4_arch: Option<&Api>,
+        arch: Option<&Api>,
+        config: Option<&KernelConfig>,
+    ) -> Result<InvocationTable, ConfigError> {
+        let default_config = KernelConfig::default();
+        let config = config.unwrap_or(&default_config);
+        let mut table = InvocationTable {
+            labels: Vec::new(),
+            by_name: HashMap::new(),
+            n_invocation_labels: 0,
+            n_sel4_arch_invocation_labels: 0,
+            n_arch_invocation_labels: 0,
+        };
+
+        // Generic labels start after InvalidInvocation.
+        table.n_invocation_labels = table.push(Layer::Generic, Some(generic), config, 1)?;
+        table.n_sel4_arch_invocation_labels = table.push(
+            Layer::Sel4Arch,
+            sel4_arch,
+            config,
+            table.n_invocation_labels,
+        )?;
+        table.n_arch_invocation_labels = table.push(
+            Layer::Arch,
+            arch,
+            config,
+            table.n_sel4_arch_invocation_labels,
+        )?;
+        Ok(table)
+    }
+
+    // Returns the value following the last label of the layer.
+    fn push(
+        &mut self,
+        layer: Layer,
+        api: Option<&Api>,
+        config: &KernelConfig,
+        first_label: usize,
+    ) -> Result<usize, ConfigError> {
+        let configured = match api {
+            Some(api) => api.configure(config, first_label)?,
+            None => return Ok(first_label),
+        };
+        let end = configured
+            .labels
+            .last()
+            .map_or(first_label, |label| label.value + 1);
+
+        // The interface and name of each remaining method, by id.
+        let methods: HashMap<String, (String, String)> = configured
+            .api
+            .children
+            .into_iter()
+            .flat_map(|child| match child {
+                ApiElement::Interface { name, methods, .. } => methods
+                    .into_iter()
+                    .map(|method| (method.id, (name.clone(), method.name)))
+                    .collect(),
+                ApiElement::StructElem { .. } => Vec::new(),
+            })
+            .collect();
+        for label in configured.labels {
+            let (interface, method) = methods[&label.id].clone();
+            self.by_name.insert(label.id.clone(), self.labels.len());
+            self.labels.push(InvocationLabel {
+                id: label.id,
+                value: label.value,
+                layer,
+                interface,
+                method,
+            });
+        }
+        Ok(end)
+    }
+
+    /// Every label in ascending order.
+    pub fn labels(&self) -> &[InvocationLabel] {
+        &self.labels
+    }
+
+    pub fn by_name(&self, id: &str) -> Option<&InvocationLabel> {
+        self.by_name.get(id).map(|&i| &self.labels[i])
+    }
+
+    pub fn by_value(&self, value: usize) -> Option<&InvocationLabel> {
+        self.labels
+            .binary_search_by_key(&value, |label| label.value)
+            .ok()
+            .map(|i| &self.labels[i])
+    }
+
+    /// The value of `nInvocationLabels`
+    pub fn n_invocation_labels(&self) -> usize {
+        self.n_invocation_labels
+    }
+
+    /// The value of `nSeL4ArchInvocationLabels`
+    pub fn n_sel4_arch_invocation_labels(&self) -> usize {
+        self.n_sel4_arch_invocation_labels
+    }
+
+    /// The value of `nArchInvocationLabels`
+    pub fn n_arch_invocation_labels(&self) -> usize {
+        self.n_arch_invocation_labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn api(xml: &str) -> Result<Api, TestError> {
+        Ok(Api::try_from(roxmltree::Document::parse(xml)?)?)
+    }
+
+    #[test]
+    fn chaining() -> Result<(), TestError> {
+        let generic = api(r#"
+<api name="ObjectApi">
+    <interface name="seL4_Untyped">
+        <method id="UntypedRetype" name="Retype"/>
+    </interface>
+    <interface name="seL4_TCB">
+        <method id="TCBReadRegisters" name="ReadRegisters"/>
+        <method id="TCBSetTimeoutEndpoint" name="SetTimeoutEndpoint" condition="defined(CONFIG_KERNEL_MCS)"/>
+        <method id="TCBSetAffinity" name="SetAffinity" condition="!defined(CONFIG_KERNEL_MCS)"/>
+    </interface>
+</api>"#)?;
+        let sel4_arch = api(r#"
+<api name="ObjectApiX64">
+    <interface name="seL4_X86_PDPT">
+        <method id="X86PDPTMap" name="Map"/>
+        <method id="X86PDPTUnmap" name="Unmap"/>
+    </interface>
+</api>"#)?;
+        let arch = api(r#"
+<api name="ObjectApiX86">
+    <interface name="seL4_X86_PageDirectory">
+        <method id="X86PageDirectoryMap" name="Map"/>
+    </interface>
+</api>"#)?;
+
+        let table = InvocationTable::new(&generic, Some(&sel4_arch), Some(&arch), None)?;
+        let values: Vec<_> = table
+            .labels()
+            .iter()
+            .map(|label| (label.id.as_str(), label.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("UntypedRetype", 1),
+                ("TCBReadRegisters", 2),
+                ("TCBSetAffinity", 3),
+                ("X86PDPTMap", 4),
+                ("X86PDPTUnmap", 5),
+                ("X86PageDirectoryMap", 6),
+            ]
+        );
+        assert_eq!(table.n_invocation_labels(), 4);
+        assert_eq!(table.n_sel4_arch_invocation_labels(), 6);
+        assert_eq!(table.n_arch_invocation_labels(), 7);
+
+        let mut config = KernelConfig::new();
+        config.define("CONFIG_KERNEL_MCS", "1");
+        let table = InvocationTable::new(&generic, None, Some(&arch), Some(&config))?;
+        let label = table.by_name("TCBSetTimeoutEndpoint").unwrap();
+        assert_eq!((label.value, label.layer), (3, Layer::Generic));
+        assert_eq!(label.interface, "seL4_TCB");
+        assert!(table.by_name("TCBSetAffinity").is_none());
+        assert_eq!(table.by_value(4).unwrap().id, "X86PageDirectoryMap");
+        assert_eq!(table.n_sel4_arch_invocation_labels(), 4);
+        assert!(table.by_value(0).is_none());
+        assert!(table.by_value(5).is_none());
+        Ok(())
+    }
+}
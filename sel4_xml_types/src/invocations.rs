@@ -1,67 +1,101 @@
+use crate::borrowed;
+use crate::config::{ConfigError, KernelConfig};
+pub use crate::error::{Location, Position, Sel4XmlError};
 use crate::invocation_helper::*;
+use crate::validate::validate;
 use roxmltree as xml;
 use serde::{Deserialize, Serialize};
-use thiserror::Error;
+use std::path::Path;
 
 //
-// While the syscall API is easily parsed with serde based xml libraries
+// While the syscall API easily fits the serde data model,
 //
-// The xml defined in sel4_idl.dtd however doesn't easily fit into the
-// serde data model. In particular the serde parsers don't deal well with
-// elements such as: `<description> text <texttt text="foo"/> <description>`
+// The xml defined in sel4_idl.dtd however does not. In particular the serde
+// parsers don't deal well with elements such as:
+// `<description> text <texttt text="foo"/> <description>`
 //
 // which would involve an `enum { Text(String), TextTT{text: String} }`
 // as a child of description.
 //
-// Instead this uses [roxmltree](https://docs.rs/roxmltree/)
-// It is unfortunate to use multiple xml parsers in this library,
-// but it is easier to just use serde when it works well.
+// Instead this uses [roxmltree](https://docs.rs/roxmltree/), as does
+// syscalls.rs so that both report a `Sel4XmlError` located in the xml.
 //
-// For now we'll just depend upon multiple parsers, if a wrapper for roxmltree
-// implements serde Deserialization this could use that instead.
+// The parser does not check that sequences/children are emitted in the correct order,
+// `Api::parse_validated` checks the whole of sel4_idl.dtd first, see validate.rs.
 //
-// The current implementation does not check that sequences/children are emitted in the correct order.
-// However this can be checked from the resulting Vec structures.
-//
-
-#[derive(Error, Debug)]
-pub enum InvocationGenError {
-    #[error("roxmltree error {0}")]
-    Roxmltree(#[from] roxmltree::Error),
-    #[error("attribute '{0}' not found")]
-    Attribute(String, &'static str, u32),
-    #[error("Cannot convert element from unknown element '{0} ")]
-    UnsupportedNodeType(String, &'static str, u32),
-}
 
 /// While the types herein implement `Deserialize`
 /// They would not Deserialize to equivalent XML.
 ///
 /// This uses a read-only XML parser, and converts the parsed xml
-/// into more (hopefully) convenient types.
-#[derive(Debug, Serialize, Deserialize)]
+/// into more (hopefully) convenient types. `Api::to_xml` writes
+/// them back, see writer.rs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Api {
+    pub position: Position,
     pub name: Option<String>,
     pub label_prefix: Option<String>,
     pub children: Vec<ApiElement>,
+    pub formatting: Formatting,
+}
+
+/// How an element was written in the xml, so that it is written back the
+/// same way. Elements created by hand have the default formatting, and are
+/// written indented by four spaces per level.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Formatting {
+    pub(crate) attributes: Vec<RawAttribute>,
+    /// From the last attribute to the end of the start tag e.g. ` />`
+    pub(crate) tag_end: String,
+    /// The whitespace and comments before each child element, keyed by
+    /// `gap_key` of the child so they stay with it when others are removed.
+    pub(crate) gaps: Vec<(String, String)>,
+    /// The whitespace and comments before the end tag.
+    pub(crate) end_gap: String,
+    /// Before and after the root element, such as `<?xml ... ?>` and the licence.
+    pub(crate) prolog: String,
+    pub(crate) epilogue: String,
+}
+
+// A child element by its tag and its `id` or `name`, a child which is
+// renamed is written as a new one.
+pub(crate) fn gap_key(tag: &str, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{} {}", tag, name),
+        None => tag.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RawAttribute {
+    pub(crate) name: String,
+    /// The whitespace before the attribute.
+    pub(crate) before: String,
+    /// `name="value"` as written, entities and line breaks included.
+    pub(crate) raw: String,
+    /// The value as parsed, while it is unchanged `raw` is written.
+    pub(crate) value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ApiElement {
     StructElem {
         name: String,
         members: Vec<String>,
+        formatting: Formatting,
     },
     Interface {
         name: String,
         manual_name: Option<String>,
         cap_desc: Option<String>,
         methods: Vec<Method>,
+        formatting: Formatting,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Method {
+    pub position: Position,
     // Attributes
     pub name: String,
     pub id: String,
@@ -75,49 +109,59 @@ pub struct Method {
     pub cap_param: Option<CapParam>,
     pub params: Vec<Param>,
     pub errors: Vec<ErrorElement>,
+    pub formatting: Formatting,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Return {
     ErrorEnumDesc,
     Leaves(DocLeaf),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Param {
-    typ: String,
-    name: String,
-    dir: String,
+    pub(crate) position: Position,
+    pub(crate) typ: String,
+    pub(crate) name: String,
+    pub(crate) dir: String,
     // From either an attribute, a child element
     // or (In a case which should perhaps be excluded)
     // if both attribute and a child <description>foo</description>
     // are set, this could contain both
-    description: Vec<DocLeaf>,
-    errors: Vec<ErrorElement>,
+    pub(crate) description: Vec<DocLeaf>,
+    // Whether the first leaf of `description` is the attribute.
+    pub(crate) description_attribute: bool,
+    pub(crate) errors: Vec<ErrorElement>,
+    pub(crate) formatting: Formatting,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorElement {
-    name: String,
+    pub(crate) position: Position,
+    pub(crate) name: String,
     // From either an attribute, a child element
     // or (In a case which should perhaps be excluded)
     // if both attribute and a child <description>foo</description>
     // are set, this could contain both
-    description: Vec<DocLeaf>,
+    pub(crate) description: Vec<DocLeaf>,
+    // Whether the first leaf of `description` is the attribute.
+    pub(crate) description_attribute: bool,
+    pub(crate) formatting: Formatting,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CapParam {
-    append_description: String,
+    pub(crate) append_description: String,
+    pub(crate) formatting: Formatting,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DocLeaf {
-    DocRef(Vec<LeafNode>),
-    Leaf(LeafNode),
+    DocRef(Vec<LeafNode>, Position),
+    Leaf(LeafNode, Position),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LeafNode {
     PCData(String),
     TextTT(String),
@@ -126,307 +170,266 @@ pub enum LeafNode {
     Obj { name: String },
 }
 
-impl<'a> TryFrom<roxmltree::Document<'a>> for Api {
-    type Error = InvocationGenError;
-    fn try_from(doc: xml::Document<'a>) -> Result<Api, Self::Error> {
-        let element = doc.root_element();
+/// An `Api` as it exists in a particular kernel configuration.
+///
+/// Methods whose condition does not hold have been removed,
+/// the remaining methods are unconditional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfiguredApi {
+    pub api: Api,
+    /// The invocation label of each remaining method, in declaration order.
+    pub labels: Vec<Label>,
+}
 
-        let name = element.attribute("name").map(str::to_string);
-        let label_prefix = element.attribute("label_prefix").map(str::to_string);
-        let mut children = Vec::new();
-        for child in element.children() {
-            let child = WhitespaceOr::<ApiElement>::try_from(child)?.ignore_whitespace();
-            if let Some(child) = child {
-                children.push(child)
-            }
-        }
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    pub id: String,
+    pub value: usize,
+}
 
-        Ok(Api {
-            name,
-            label_prefix,
-            children,
-        })
+impl Param {
+    pub fn position(&self) -> Position {
+        self.position
     }
-}
 
-impl TryFrom<roxmltree::Node<'_, '_>> for Return {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<Return, InvocationGenError> {
-        match node.node_type() {
-            roxmltree::NodeType::Element => match node.tag_name().name().to_lowercase() {
-                s if s == "errorenumdesc" => Ok(Return::ErrorEnumDesc),
-                _ => Ok(Return::Leaves(DocLeaf::try_from(node)?)),
-            },
-            _ => Ok(Return::Leaves(DocLeaf::try_from(node)?)),
-        }
+    /// The C type name e.g. `seL4_Word`
+    pub fn typ(&self) -> &str {
+        &self.typ
     }
-}
 
-impl TryFrom<roxmltree::Node<'_, '_>> for ErrorElement {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<ErrorElement, InvocationGenError> {
-        let name = node.req_attr("name")?;
-        let description_attr = node.opt_attr("description");
-        let mut description = Vec::new();
-
-        if let Some(description_attr) = description_attr {
-            description.push(DocLeaf::Leaf(LeafNode::PCData(description_attr)))
-        }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-        if let Some(node) = skip_irrelevant(node.first_child()) {
-            let _node = take_leaves_from_element(node, "description", &mut description)?;
-        }
+    /// Either `in` or `out`
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+
+    pub fn description(&self) -> &[DocLeaf] {
+        &self.description
+    }
 
-        Ok(ErrorElement { name, description })
+    pub fn errors(&self) -> &[ErrorElement] {
+        &self.errors
     }
 }
 
-impl TryFrom<roxmltree::Node<'_, '_>> for DocLeaf {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<DocLeaf, InvocationGenError> {
-        Ok(match node.node_type() {
-            roxmltree::NodeType::Element if node.tag_name().name().to_lowercase() == "docref" => {
-                let mut leaves = Vec::new();
-
-                for leaf in node.children() {
-                    let leaf = LeafNode::try_from(leaf)?;
-                    leaves.push(leaf)
-                }
+impl ErrorElement {
+    pub fn position(&self) -> Position {
+        self.position
+    }
 
-                DocLeaf::DocRef(leaves)
-            }
-            _ => DocLeaf::Leaf(LeafNode::try_from(node)?),
-        })
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &[DocLeaf] {
+        &self.description
     }
 }
 
-impl TryFrom<roxmltree::Node<'_, '_>> for LeafNode {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<LeafNode, Self::Error> {
-        if node.is_text() {
-            Ok(LeafNode::PCData(node.text().unwrap().to_string()))
-        } else {
-            let tag_name = node.tag_name().name().to_lowercase();
-            match node.node_type() {
-                roxmltree::NodeType::Element if tag_name == "texttt" => {
-                    Ok(LeafNode::TextTT(node.req_attr("text")?))
-                }
-                roxmltree::NodeType::Element if tag_name == "shortref" => Ok(LeafNode::ShortRef {
-                    sec: node.req_attr("sec")?,
-                }),
-                roxmltree::NodeType::Element if tag_name == "autoref" => Ok(LeafNode::ShortRef {
-                    sec: node.req_attr("label")?,
-                }),
-                roxmltree::NodeType::Element if tag_name == "obj" => Ok(LeafNode::Obj {
-                    name: node.req_attr("name")?,
-                }),
-                _ => Err(InvocationGenError::UnsupportedNodeType(
-                    node.tag_name().name().to_string(),
-                    file!(),
-                    line!(),
-                )),
-            }
+impl DocLeaf {
+    pub fn position(&self) -> Position {
+        match self {
+            DocLeaf::DocRef(_, position) | DocLeaf::Leaf(_, position) => *position,
         }
     }
 }
 
-impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for Method {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, InvocationGenError> {
-        // handle attributes
-        let name = node.req_attr("name")?;
-        let id = node.req_attr("id")?;
-        let condition = node.opt_attr("condition");
-        let manual_name = node.opt_attr("manual_name");
-        let manual_label = node.opt_attr("manual_label");
-
-        // handle children
-        let mut brief = Vec::new();
-        let node = node.first_child();
-        let node = if let Some(node) = skip_irrelevant(node) {
-            take_leaves_from_element(node, "brief", &mut brief)?
-        } else {
-            None
-        };
+impl CapParam {
+    /// Text appended to the interface's `cap_description`
+    /// when documenting the invoked capability.
+    pub fn append_description(&self) -> &str {
+        &self.append_description
+    }
+}
 
-        let mut description = Vec::new();
-        let node = if let Some(node) = skip_irrelevant(node) {
-            take_leaves_from_element(node, "description", &mut description)?
-        } else {
-            None
-        };
+impl Api {
+    /// Parses the IDL in `text`, errors refer to it as `file`.
+    pub fn parse(file: &Path, text: &str) -> Result<Api, Sel4XmlError> {
+        let doc = roxmltree::Document::parse(text)
+            .map_err(|err| Sel4XmlError::Xml(file.to_path_buf(), err))?;
+        Api::try_from(doc).map_err(|err| err.in_file(file))
+    }
 
-        let mut return_value = Vec::new();
-        let node = if let Some(node) = skip_irrelevant(node) {
-            take_return_value_from_element(node, &mut return_value)?
+    /// Like `parse`, but keeps going past methods, params and doc leaves
+    /// which fail to parse. They are left out of the `Api`, and their
+    /// errors are returned alongside it.
+    ///
+    /// Only xml which isn't well formed fails the whole parse.
+    pub fn parse_recovering(
+        file: &Path,
+        text: &str,
+    ) -> Result<(Api, Vec<Sel4XmlError>), Sel4XmlError> {
+        let doc = roxmltree::Document::parse(text)
+            .map_err(|err| Sel4XmlError::Xml(file.to_path_buf(), err))?;
+        let mut recovery = Recovery::recovering();
+        let api = Api::from_document(&doc, &mut recovery).map_err(|err| err.in_file(file))?;
+        let errors = recovery
+            .errors
+            .into_iter()
+            .map(|err| err.in_file(file))
+            .collect();
+        Ok((api, errors))
+    }
+
+    /// Like `parse_recovering`, but the xml must follow sel4_idl.dtd.
+    ///
+    /// When it doesn't, the errors are those of the validation, which
+    /// say more than those of the lenient parse.
+    pub fn parse_validated(
+        file: &Path,
+        text: &str,
+    ) -> Result<(Api, Vec<Sel4XmlError>), Sel4XmlError> {
+        let (api, errors) = Api::parse_recovering(file, text)?;
+        let doc = roxmltree::Document::parse(text)?;
+        let invalid = validate(&doc, Some(file));
+        if invalid.is_empty() {
+            Ok((api, errors))
         } else {
-            None
-        };
+            Ok((api, invalid.into_iter().map(Sel4XmlError::from).collect()))
+        }
+    }
 
-        let mut cap_param = None;
-        let node = if let Some(node) = skip_irrelevant(node) {
-            match node.node_type() {
-                roxmltree::NodeType::Element
-                    if node.tag_name().name().to_lowercase() == "cap_param" =>
-                {
-                    cap_param = node.opt_attr("append_description").map(|text| CapParam {
-                        append_description: text,
+    /// Evaluates the condition of every method against `config`.
+    ///
+    /// Labels are numbered from `first_label` upward, the way the C enum would
+    /// number them. For the values the kernel uses, sel4.xml starts at `1` since
+    /// `InvalidInvocation` comes first, see `InvocationTable`. Callers after the
+    /// configured api alone can pass `0`.
+    pub fn configure(
+        &self,
+        config: &KernelConfig,
+        first_label: usize,
+    ) -> Result<ConfiguredApi, ConfigError> {
+        let mut labels = Vec::new();
+        let mut children = Vec::new();
+        for child in &self.children {
+            match child {
+                ApiElement::Interface {
+                    name,
+                    manual_name,
+                    cap_desc,
+                    methods,
+                    formatting,
+                } => {
+                    let mut configured = Vec::new();
+                    for method in methods {
+                        if config.holds(method.condition.as_deref())? {
+                            labels.push(Label {
+                                id: method.id.clone(),
+                                value: first_label + labels.len(),
+                            });
+                            configured.push(Method {
+                                condition: None,
+                                ..method.clone()
+                            });
+                        }
+                    }
+                    children.push(ApiElement::Interface {
+                        name: name.clone(),
+                        manual_name: manual_name.clone(),
+                        cap_desc: cap_desc.clone(),
+                        methods: configured,
+                        formatting: formatting.clone(),
                     });
-                    node.next_sibling()
                 }
-                _ => Some(node),
-            }
-        } else {
-            None
-        };
-
-        let mut params = Vec::new();
-        let mut node = node;
-        while let Some(a_node) = skip_irrelevant(node) {
-            if a_node.node_type() == roxmltree::NodeType::Element
-                && a_node.tag_name().name().to_lowercase() == "param"
-            {
-                params.push(Param::try_from(a_node)?);
-                node = a_node.next_sibling();
-            } else {
-                break;
+                child => children.push(child.clone()),
             }
         }
-        let errors = Vec::new();
-
-        Ok(Method {
-            name,
-            id,
-            condition,
-            manual_name,
-            manual_label,
-            brief,
-            description,
-            return_value,
-            cap_param,
-            params,
-            errors,
+
+        Ok(ConfiguredApi {
+            api: Api {
+                children,
+                ..self.clone()
+            },
+            labels,
         })
     }
 }
 
-impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for Param {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, InvocationGenError> {
-        match node.node_type() {
-            xml::NodeType::Element => match node.tag_name().name().to_lowercase() {
-                s if s == "param" => {
-                    let typ = node.req_attr("type")?;
-                    let name = node.req_attr("name")?;
-                    let dir = node.req_attr("dir")?;
-                    let description_attr = node.opt_attr("description");
-                    let node = node.first_child();
-                    let mut description = Vec::new();
-                    if let Some(description_attr) = description_attr {
-                        description.push(DocLeaf::Leaf(LeafNode::PCData(description_attr)));
-                    }
+// The parsing itself is done by the borrowed model, see borrowed.rs.
 
-                    let mut node = if let Some(node) = skip_irrelevant(node) {
-                        take_leaves_from_element(node, "description", &mut description)?
-                    } else {
-                        None
-                    };
-                    let mut errors = Vec::new();
-                    while let Some(child) = skip_irrelevant(node) {
-                        let error = ErrorElement::try_from(child)?;
-                        errors.push(error);
-                        node = child.next_sibling();
-                    }
+impl<'a> TryFrom<roxmltree::Document<'a>> for Api {
+    type Error = Sel4XmlError;
+    fn try_from(doc: xml::Document<'a>) -> Result<Api, Self::Error> {
+        Api::from_document(&doc, &mut Recovery::strict())
+    }
+}
 
-                    Ok(Param {
-                        typ,
-                        name,
-                        dir,
-                        description,
-                        errors,
-                    })
-                }
-                _ => Err(InvocationGenError::UnsupportedNodeType(
-                    node.tag_name().name().to_string(),
-                    file!(),
-                    line!(),
-                )),
-            },
-            _ => Err(InvocationGenError::UnsupportedNodeType(
-                node.tag_name().name().to_string(),
-                file!(),
-                line!(),
-            )),
-        }
+impl Api {
+    fn from_document(
+        doc: &xml::Document<'_>,
+        recovery: &mut Recovery,
+    ) -> Result<Api, Sel4XmlError> {
+        borrowed::Api::parse_document(doc, recovery).map(borrowed::Api::into_owned)
     }
 }
 
-impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for ApiElement {
-    type Error = InvocationGenError;
-    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, Self::Error> {
-        match node.tag_name().name().to_lowercase() {
-            s if s == "struct" => {
-                let name = node.req_attr("name")?;
-                let mut members = Vec::new();
-
-                for child in node.children() {
-                    if let roxmltree::NodeType::Element = child.node_type() {
-                        let child_name = child.req_attr("name")?;
-                        members.push(child_name);
-                    }
-                }
+impl TryFrom<roxmltree::Node<'_, '_>> for Return {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<Return, Sel4XmlError> {
+        borrowed::Return::try_from(node).map(borrowed::Return::into_owned)
+    }
+}
 
-                Ok(ApiElement::StructElem { name, members })
-            }
+impl TryFrom<roxmltree::Node<'_, '_>> for ErrorElement {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<ErrorElement, Sel4XmlError> {
+        borrowed::ErrorElement::from_node(node, &mut Recovery::strict())
+            .map(borrowed::ErrorElement::into_owned)
+    }
+}
 
-            s if s == "interface" => {
-                let name = node.req_attr("name")?;
-                let manual_name = node.opt_attr("manual_name");
-                let cap_desc = node.opt_attr("capability_description");
-                let mut methods = Vec::new();
-                for child in node.children() {
-                    let child = WhitespaceOr::<Method>::try_from(child)?.ignore_whitespace();
-                    if let Some(child) = child {
-                        methods.push(child);
-                    }
-                }
+impl TryFrom<roxmltree::Node<'_, '_>> for DocLeaf {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<DocLeaf, Sel4XmlError> {
+        borrowed::DocLeaf::try_from(node).map(borrowed::DocLeaf::into_owned)
+    }
+}
 
-                Ok(ApiElement::Interface {
-                    name,
-                    manual_name,
-                    cap_desc,
-                    methods,
-                })
-            }
+impl TryFrom<roxmltree::Node<'_, '_>> for LeafNode {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'_, '_>) -> Result<LeafNode, Self::Error> {
+        borrowed::LeafNode::try_from(node).map(borrowed::LeafNode::into_owned)
+    }
+}
 
-            s => Err(InvocationGenError::UnsupportedNodeType(s, file!(), line!())),
-        }
+impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for Method {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, Sel4XmlError> {
+        borrowed::Method::from_node(node, &mut Recovery::strict()).map(borrowed::Method::into_owned)
+    }
+}
+
+impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for Param {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, Sel4XmlError> {
+        borrowed::Param::from_node(node, &mut Recovery::strict()).map(borrowed::Param::into_owned)
+    }
+}
+
+impl<'a, 'b> TryFrom<roxmltree::Node<'a, 'b>> for ApiElement {
+    type Error = Sel4XmlError;
+    fn try_from(node: roxmltree::Node<'a, 'b>) -> Result<Self, Self::Error> {
+        borrowed::ApiElement::from_node(node, &mut Recovery::strict())
+            .map(borrowed::ApiElement::into_owned)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kernel_tree::KernelTree;
     use crate::test_support::*;
     use std::fs::File;
     use std::io::{BufReader, Read};
 
     #[test]
     fn test() -> Result<(), TestError> {
-        let files = [
-            "../../../kernel/libsel4/arch_include/x86/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/arch_include/arm/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/arch_include/riscv/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/include/interfaces/sel4.xml",
-            "../../../kernel/libsel4/sel4_arch_include/aarch32/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/sel4_arch_include/aarch64/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/sel4_arch_include/ia32/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/sel4_arch_include/riscv32/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/sel4_arch_include/riscv64/interfaces/sel4arch.xml",
-            "../../../kernel/libsel4/sel4_arch_include/x86_64/interfaces/sel4arch.xml",
-        ];
-
-        for filename in files {
+        let tree = KernelTree::discover("../../../kernel")?;
+        for filename in tree.interface_files() {
             let f = File::open(filename)?;
             let mut reader = BufReader::new(f);
             let mut s = String::new();
@@ -437,4 +440,191 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn interface_attributes() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB" manual_name="TCB" cap_description="Capability to the TCB which is being operated on.">
+        <method id="TCBSuspend" name="Suspend"/>
+    </interface>
+</api>"#;
+        let api = Api::parse(Path::new("sel4.xml"), xml)?;
+        match &api.children[0] {
+            ApiElement::Interface {
+                name,
+                manual_name,
+                cap_desc,
+                ..
+            } => {
+                assert_eq!(name, "seL4_TCB");
+                assert_eq!(manual_name.as_deref(), Some("TCB"));
+                assert_eq!(
+                    cap_desc.as_deref(),
+                    Some("Capability to the TCB which is being operated on.")
+                );
+            }
+            _ => panic!("expected an interface"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn recovering() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSuspend">
+        </method>
+        <method id="TCBResume" name="Resume">
+            <brief>Resume <textt text="tcb"/></brief>
+            <param dir="in" name="a" type="seL4_Word"/>
+            <param dir="in" type="seL4_Word"/>
+            <param dir="in" name="c" type="seL4_Word"/>
+        </method>
+    </interface>
+</api>"#;
+        let (api, errors) = Api::parse_recovering(Path::new("sel4.xml"), xml)?;
+        let methods = match &api.children[0] {
+            ApiElement::Interface { methods, .. } => methods,
+            _ => panic!("expected an interface"),
+        };
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods[0].brief.len(), 1);
+        let params: Vec<_> = methods[0].params.iter().map(Param::name).collect();
+        assert_eq!(params, ["a", "c"]);
+
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| err.location().map(|location| location.position.line))
+            .collect();
+        assert_eq!(errors, [Some(3), Some(6), Some(8)]);
+
+        // The strict parse stops at the first.
+        assert!(Api::parse(Path::new("sel4.xml"), xml).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn method_errors() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSetPriority" name="SetPriority">
+            <param dir="in" name="priority" type="seL4_Word">
+                <error name="seL4_RangeError"/>
+            </param>
+            <!-- Errors of the method as a whole -->
+            <error name="seL4_IllegalOperation" description="The TCB is not valid."/>
+            <error name="seL4_InvalidCapability">
+                <description>The <texttt text="authority"/> is not a TCB.</description>
+            </error>
+        </method>
+    </interface>
+</api>"#;
+        let api = Api::parse(Path::new("sel4.xml"), xml)?;
+        let method = match &api.children[0] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        let param_errors: Vec<_> = method.params[0]
+            .errors()
+            .iter()
+            .map(ErrorElement::name)
+            .collect();
+        assert_eq!(param_errors, ["seL4_RangeError"]);
+        let errors: Vec<_> = method.errors.iter().map(ErrorElement::name).collect();
+        assert_eq!(errors, ["seL4_IllegalOperation", "seL4_InvalidCapability"]);
+        assert_eq!(method.errors[0].description().len(), 1);
+        assert_eq!(method.errors[1].description().len(), 3);
+        assert_eq!(method.errors[1].position().line, 9);
+
+        // Anything else after the params is an error rather than ignored.
+        let xml = xml.replace("<error name=\"seL4_Ill", "<eror name=\"seL4_Ill");
+        let (api, errors) = Api::parse_recovering(Path::new("sel4.xml"), &xml)?;
+        let method = match &api.children[0] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        assert_eq!(method.errors.len(), 1);
+        assert!(matches!(
+            &errors[..],
+            [Sel4XmlError::UnsupportedNodeType(name, _)] if name == "eror"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn positions() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBConfigure" name="Configure">
+            <param dir="in" name="fault_ep" type="seL4_Word"/>
+            <param dir="in" type="seL4_CNode"
+                description="The new CSpace root."/>
+        </method>
+    </interface>
+</api>"#;
+        let err = Api::parse(Path::new("sel4.xml"), xml).unwrap_err();
+        let location = err.location().expect("a located error");
+        assert_eq!(
+            location.position,
+            Position {
+                line: 5,
+                column: 13
+            }
+        );
+        assert_eq!(
+            location.path,
+            "interface[@name=seL4_TCB]/method[@id=TCBConfigure]/param[2]"
+        );
+        let start_tag = &xml[location.span.clone()];
+        assert!(start_tag.starts_with("<param dir=\"in\" type"));
+        assert!(start_tag.ends_with("root.\"/>"));
+        assert_eq!(
+            err.to_string(),
+            "sel4.xml:5:13 interface[@name=seL4_TCB]/method[@id=TCBConfigure]/param[2]: \
+             attribute 'name' not found"
+        );
+
+        let api = Api::parse(
+            Path::new("sel4.xml"),
+            &xml.replace(
+                "<param dir=\"in\" type",
+                "<param dir=\"in\" name=\"root\" type",
+            ),
+        )?;
+        assert_eq!(api.position, Position { line: 1, column: 1 });
+        let method = match &api.children[0] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        assert_eq!(method.position, Position { line: 3, column: 9 });
+        assert_eq!(
+            method.params[1].position(),
+            Position {
+                line: 5,
+                column: 13
+            }
+        );
+        assert_eq!(
+            method.params[1].description()[0].position(),
+            Position {
+                line: 6,
+                column: 17
+            }
+        );
+
+        // A `>` in a condition doesn't end the start tag.
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSetAffinity" condition="CONFIG_MAX_NUM_NODES > 1">
+        </method>
+    </interface>
+</api>"#;
+        let err = Api::parse(Path::new("sel4.xml"), xml).unwrap_err();
+        let location = err.location().expect("a located error");
+        assert_eq!(
+            &xml[location.span.clone()],
+            r#"<method id="TCBSetAffinity" condition="CONFIG_MAX_NUM_NODES > 1">"#
+        );
+        Ok(())
+    }
 }
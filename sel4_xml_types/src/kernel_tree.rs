@@ -0,0 +1,338 @@
+use crate::arch::{Arch, Sel4Arch};
+use crate::config::{ConfigError, KernelConfig};
+use crate::error::Sel4XmlError;
+use crate::invocation_table::InvocationTable;
+use crate::invocations::Api;
+use crate::syscalls::Syscalls;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use thiserror::Error;
+
+//
+// Finds the xml files of an seL4 checkout, so that tools can load those
+// of an architecture without knowing the layout of libsel4:
+//
+//   libsel4/include/api/syscall.xml
+//   libsel4/include/interfaces/sel4.xml
+//   libsel4/arch_include/<arch>/interfaces/sel4arch.xml
+//   libsel4/sel4_arch_include/<sel4_arch>/interfaces/sel4arch.xml
+//
+// The architecture of each sel4_arch is not written down anywhere in the
+// tree but the CMake files, it is `Sel4Arch::arch`.
+//
+
+#[derive(Error, Debug)]
+pub enum KernelTreeError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Xml(#[from] Sel4XmlError),
+    #[error("{} is not an seL4 checkout, {} not found", .0.display(), .1.display())]
+    NotFound(PathBuf, PathBuf),
+    #[error("unknown sel4_arch '{0}'")]
+    UnknownSel4Arch(String),
+    #[error("no interfaces for sel4_arch '{0}'")]
+    MissingSel4Arch(Sel4Arch),
+}
+
+/// The xml files of an seL4 checkout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelTree {
+    pub root: PathBuf,
+    pub syscall_xml: PathBuf,
+    /// The interfaces common to every architecture.
+    pub sel4_xml: PathBuf,
+    pub arches: Vec<ArchTree>,
+}
+
+/// The interfaces of an architecture, and of each of its sel4_arches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchTree {
+    pub arch: Arch,
+    pub interfaces: PathBuf,
+    pub sel4_arches: Vec<Sel4ArchTree>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sel4ArchTree {
+    pub sel4_arch: Sel4Arch,
+    pub interfaces: PathBuf,
+}
+
+/// The interface files which make up the api of a sel4_arch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceFiles {
+    pub sel4_arch: Sel4Arch,
+    pub arch: Arch,
+    pub sel4_xml: PathBuf,
+    pub sel4_arch_xml: PathBuf,
+    pub arch_xml: PathBuf,
+}
+
+/// Everything a sel4_arch is generated from, parsed.
+#[derive(Debug, Clone)]
+pub struct KernelApis {
+    pub files: InterfaceFiles,
+    pub syscalls: Syscalls,
+    pub generic: Api,
+    pub sel4_arch: Api,
+    pub arch: Api,
+}
+
+impl KernelTree {
+    /// Finds the xml files under `root`, the top of an seL4 checkout.
+    pub fn discover<P: AsRef<Path>>(root: P) -> Result<KernelTree, KernelTreeError> {
+        let root = root.as_ref();
+        let libsel4 = root.join("libsel4");
+        let syscall_xml = existing(root, libsel4.join("include/api/syscall.xml"))?;
+        let sel4_xml = existing(root, libsel4.join("include/interfaces/sel4.xml"))?;
+
+        let mut arches: Vec<ArchTree> = Vec::new();
+        for sel4_arch in subdirectories(&libsel4.join("sel4_arch_include"))? {
+            let interfaces = libsel4
+                .join("sel4_arch_include")
+                .join(&sel4_arch)
+                .join("interfaces/sel4arch.xml");
+            if !interfaces.is_file() {
+                continue;
+            }
+            let sel4_arch: Sel4Arch = sel4_arch
+                .parse()
+                .map_err(|_| KernelTreeError::UnknownSel4Arch(sel4_arch))?;
+            let arch = sel4_arch.arch();
+            let sel4_arch = Sel4ArchTree {
+                sel4_arch,
+                interfaces,
+            };
+            match arches.iter_mut().find(|tree| tree.arch == arch) {
+                Some(tree) => tree.sel4_arches.push(sel4_arch),
+                None => arches.push(ArchTree {
+                    arch,
+                    interfaces: existing(
+                        root,
+                        libsel4
+                            .join("arch_include")
+                            .join(arch.name())
+                            .join("interfaces/sel4arch.xml"),
+                    )?,
+                    sel4_arches: vec![sel4_arch],
+                }),
+            }
+        }
+        arches.sort_by_key(|tree| tree.arch);
+        for tree in &mut arches {
+            tree.sel4_arches.sort_by_key(|tree| tree.sel4_arch);
+        }
+
+        Ok(KernelTree {
+            root: root.to_path_buf(),
+            syscall_xml,
+            sel4_xml,
+            arches,
+        })
+    }
+
+    pub fn arch(&self, arch: Arch) -> Option<&ArchTree> {
+        self.arches.iter().find(|tree| tree.arch == arch)
+    }
+
+    /// The interface files of `sel4_arch`, if it is in the tree.
+    pub fn interfaces(&self, sel4_arch: Sel4Arch) -> Option<InterfaceFiles> {
+        let arch = self.arch(sel4_arch.arch())?;
+        arch.sel4_arches
+            .iter()
+            .find(|tree| tree.sel4_arch == sel4_arch.libsel4_sel4_arch())
+            .map(|tree| InterfaceFiles {
+                sel4_arch,
+                arch: arch.arch,
+                sel4_xml: self.sel4_xml.clone(),
+                sel4_arch_xml: tree.interfaces.clone(),
+                arch_xml: arch.interfaces.clone(),
+            })
+    }
+
+    /// Every interface file, each once: sel4.xml then those of each
+    /// architecture followed by its sel4_arches.
+    pub fn interface_files(&self) -> Vec<&Path> {
+        let mut files = vec![self.sel4_xml.as_path()];
+        for arch in &self.arches {
+            files.push(&arch.interfaces);
+            files.extend(
+                arch.sel4_arches
+                    .iter()
+                    .map(|tree| tree.interfaces.as_path()),
+            );
+        }
+        files
+    }
+
+    /// Parses the syscalls and the interfaces of `sel4_arch`.
+    pub fn load(&self, sel4_arch: Sel4Arch) -> Result<KernelApis, KernelTreeError> {
+        let files = self
+            .interfaces(sel4_arch)
+            .ok_or(KernelTreeError::MissingSel4Arch(sel4_arch))?;
+        let syscalls = Syscalls::parse(&self.syscall_xml, &fs::read_to_string(&self.syscall_xml)?)?;
+        let parse = |file: &Path| -> Result<Api, KernelTreeError> {
+            Ok(Api::parse(file, &fs::read_to_string(file)?)?)
+        };
+        Ok(KernelApis {
+            syscalls,
+            generic: parse(&files.sel4_xml)?,
+            sel4_arch: parse(&files.sel4_arch_xml)?,
+            arch: parse(&files.arch_xml)?,
+            files,
+        })
+    }
+}
+
+impl KernelApis {
+    /// The labels of the three layers as the C enums number them, sel4.xml
+    /// first then the sel4_arch and the arch.
+    pub fn invocation_table(
+        &self,
+        config: Option<&KernelConfig>,
+    ) -> Result<InvocationTable, ConfigError> {
+        InvocationTable::new(
+            &self.generic,
+            Some(&self.sel4_arch),
+            Some(&self.arch),
+            config,
+        )
+    }
+}
+
+fn existing(root: &Path, path: PathBuf) -> Result<PathBuf, KernelTreeError> {
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(KernelTreeError::NotFound(root.to_path_buf(), path))
+    }
+}
+
+// The names of the directories in `dir`, sorted.
+fn subdirectories(dir: &Path) -> Result<Vec<String>, KernelTreeError> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    const SYSCALL_XML: &str = r#"<syscalls>
+    <api-master><config><syscall name="Call"/></config></api-master>
+    <api-mcs><config><syscall name="Call"/></config></api-mcs>
+    <debug><config><syscall name="DebugPutChar"/></config></debug>
+</syscalls>"#;
+
+    fn write(root: &Path, file: &str, text: &str) -> Result<(), TestError> {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().expect("a parent directory"))?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    #[test]
+    fn discover() -> Result<(), TestError> {
+        let root = std::env::temp_dir().join(format!("kernel_tree-{}", std::process::id()));
+        let interface = |name: &str| {
+            format!(
+                r#"<api><interface name="{0}"><method id="{0}Map" name="Map"/></interface></api>"#,
+                name
+            )
+        };
+        write(&root, "libsel4/include/api/syscall.xml", SYSCALL_XML)?;
+        write(
+            &root,
+            "libsel4/include/interfaces/sel4.xml",
+            &interface("seL4_TCB"),
+        )?;
+        for (dir, name) in [
+            ("arch_include/arm", "seL4_ARM_Page"),
+            ("arch_include/x86", "seL4_X86_Page"),
+            ("sel4_arch_include/aarch32", "seL4_ARM_PageDirectory"),
+            ("sel4_arch_include/aarch64", "seL4_ARM_VSpace"),
+            ("sel4_arch_include/x86_64", "seL4_X86_PDPT"),
+        ] {
+            let file = format!("libsel4/{}/interfaces/sel4arch.xml", dir);
+            write(&root, &file, &interface(name))?;
+        }
+        // Without an interfaces directory, as libsel4 has for arm_hyp.
+        fs::create_dir_all(root.join("libsel4/sel4_arch_include/arm_hyp"))?;
+
+        let discovered = KernelTree::discover(&root).and_then(|tree| {
+            let apis = tree.load(Sel4Arch::Aarch64)?;
+            Ok((tree, apis))
+        });
+        fs::remove_dir_all(&root)?;
+        let (tree, apis) = discovered?;
+
+        let arches: Vec<(Arch, Vec<Sel4Arch>)> = tree
+            .arches
+            .iter()
+            .map(|arch| {
+                let sel4_arches = arch.sel4_arches.iter().map(|tree| tree.sel4_arch);
+                (arch.arch, sel4_arches.collect())
+            })
+            .collect();
+        assert_eq!(
+            arches,
+            [
+                (Arch::Arm, vec![Sel4Arch::Aarch32, Sel4Arch::Aarch64]),
+                (Arch::X86, vec![Sel4Arch::X86_64])
+            ]
+        );
+        assert_eq!(tree.interface_files().len(), 6);
+        assert!(tree.interfaces(Sel4Arch::Riscv64).is_none());
+        let arm_hyp = tree
+            .interfaces(Sel4Arch::ArmHyp)
+            .expect("arm_hyp interfaces");
+        assert!(arm_hyp
+            .sel4_arch_xml
+            .ends_with("aarch32/interfaces/sel4arch.xml"));
+
+        assert_eq!(apis.files.arch, Arch::Arm);
+        let name = |api: &Api| match &api.children[0] {
+            crate::invocations::ApiElement::Interface { name, .. } => name.clone(),
+            _ => panic!("expected an interface"),
+        };
+        assert_eq!(name(&apis.generic), "seL4_TCB");
+        assert_eq!(name(&apis.arch), "seL4_ARM_Page");
+        assert_eq!(name(&apis.sel4_arch), "seL4_ARM_VSpace");
+        assert_eq!(apis.syscalls.numbered(false, true).len(), 2);
+
+        let table = apis.invocation_table(None)?;
+        let labels: Vec<_> = table
+            .labels()
+            .iter()
+            .map(|label| (label.id.as_str(), label.value))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("seL4_TCBMap", 1),
+                ("seL4_ARM_VSpaceMap", 2),
+                ("seL4_ARM_PageMap", 3)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_kernel() {
+        let err = KernelTree::discover("/nonexistent").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "/nonexistent is not an seL4 checkout, \
+             /nonexistent/libsel4/include/api/syscall.xml not found"
+        );
+    }
+}
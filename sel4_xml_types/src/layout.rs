@@ -0,0 +1,321 @@
+use crate::invocations::Method;
+use crate::types::{ParamType, TypeError, TypeKind, TypeTable};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+//
+// The message register layout of an invocation, as computed by
+// get_parameter_positions in syscall_stub_gen.py.
+//
+// Params are packed in declaration order, each aligned to its own size or the
+// word size whichever is smaller. So a param either starts on a word boundary
+// or fits within a single word. Cap params other than the invoked cap travel
+// as extra caps and take no message registers.
+//
+
+/// `seL4_MsgMaxLength`
+pub const MSG_MAX_LENGTH: usize = 120;
+/// `seL4_MsgMaxExtraCaps`
+pub const MSG_MAX_EXTRA_CAPS: usize = 3;
+
+#[derive(Error, Debug)]
+pub enum LayoutError {
+    #[error("{0}")]
+    Type(#[from] TypeError),
+    #[error("param '{param}' of method '{method}' has unknown direction '{dir}'")]
+    Direction {
+        method: String,
+        param: String,
+        dir: String,
+    },
+    #[error("type '{0}' of {1} bits is neither a power of two nor a multiple of the word size")]
+    Size(String, usize),
+}
+
+/// How part of a param is stored in a message register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Slot {
+    /// The whole value fills the register.
+    Whole,
+    /// The value is packed with others, `bits` wide starting from bit `offset`.
+    Bits { offset: usize, bits: usize },
+    /// One half of a 64 bit value on a 32 bit architecture.
+    DoubleWord { high: bool },
+    /// The n'th word of a multi-word struct or bitfield.
+    Word(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Piece {
+    /// The message register.
+    pub mr: usize,
+    pub slot: Slot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamLayout {
+    pub name: String,
+    pub typ: ParamType,
+    /// Empty for extra caps.
+    pub pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodLayout {
+    pub word_size: usize,
+    /// The `in` params in declaration order, extra caps included.
+    pub inputs: Vec<ParamLayout>,
+    pub outputs: Vec<ParamLayout>,
+    /// Names of the params sent as extra caps, in `seL4_SetCap` order.
+    pub extra_caps: Vec<String>,
+    /// Message registers used by the inputs, the length of the message info.
+    pub input_length: usize,
+    /// Message registers used by the outputs.
+    pub output_length: usize,
+}
+
+impl MethodLayout {
+    /// Lays out the params of `method`, looking up their types in `types`.
+    pub fn new(method: &Method, types: &TypeTable) -> Result<MethodLayout, LayoutError> {
+        let word_size = types.word_size();
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        let mut extra_caps = Vec::new();
+        let mut input_bits = 0;
+        let mut output_bits = 0;
+
+        for param in &method.params {
+            let typ = types.resolve(method, param)?;
+            let name = param.name().to_string();
+            match param.dir() {
+                "in" if typ.kind == TypeKind::Cap => {
+                    extra_caps.push(name.clone());
+                    inputs.push(ParamLayout {
+                        name,
+                        typ: typ.clone(),
+                        pieces: Vec::new(),
+                    });
+                }
+                "in" => inputs.push(ParamLayout {
+                    name,
+                    typ: typ.clone(),
+                    pieces: place(typ, &mut input_bits, word_size)?,
+                }),
+                "out" => outputs.push(ParamLayout {
+                    name,
+                    typ: typ.clone(),
+                    pieces: place(typ, &mut output_bits, word_size)?,
+                }),
+                dir => {
+                    return Err(LayoutError::Direction {
+                        method: method.id.clone(),
+                        param: param.name().to_string(),
+                        dir: dir.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(MethodLayout {
+            word_size,
+            inputs,
+            outputs,
+            extra_caps,
+            input_length: input_bits.div_ceil(word_size),
+            output_length: output_bits.div_ceil(word_size),
+        })
+    }
+
+    /// The pieces of the input params making up each message register.
+    pub fn input_words(&self) -> Vec<Vec<(&ParamLayout, Piece)>> {
+        let mut words = vec![Vec::new(); self.input_length];
+        for param in &self.inputs {
+            for piece in &param.pieces {
+                words[piece.mr].push((param, *piece));
+            }
+        }
+        words
+    }
+
+    /// Whether either message is longer than `seL4_MsgMaxLength`
+    pub fn exceeds_msg_max_length(&self) -> bool {
+        self.input_length.max(self.output_length) > MSG_MAX_LENGTH
+    }
+
+    /// Whether more caps are sent than `seL4_MsgMaxExtraCaps`
+    pub fn exceeds_msg_max_extra_caps(&self) -> bool {
+        self.extra_caps.len() > MSG_MAX_EXTRA_CAPS
+    }
+}
+
+// Places a param of type `typ` after `bits_used`.
+fn place(
+    typ: &ParamType,
+    bits_used: &mut usize,
+    word_size: usize,
+) -> Result<Vec<Piece>, LayoutError> {
+    let size = typ.size_bits;
+    if !size.is_power_of_two() && !size.is_multiple_of(word_size) {
+        return Err(LayoutError::Size(typ.name.clone(), size));
+    }
+
+    let align = size.min(word_size);
+    *bits_used = bits_used.div_ceil(align) * align;
+    let mr = *bits_used / word_size;
+    let offset = *bits_used % word_size;
+    *bits_used += size;
+
+    Ok(if typ.double_word {
+        vec![
+            Piece {
+                mr,
+                slot: Slot::DoubleWord { high: false },
+            },
+            Piece {
+                mr: mr + 1,
+                slot: Slot::DoubleWord { high: true },
+            },
+        ]
+    } else if size > word_size {
+        (0..size / word_size)
+            .map(|i| Piece {
+                mr: mr + i,
+                slot: Slot::Word(i),
+            })
+            .collect()
+    } else if size == word_size && typ.kind == TypeKind::BitField {
+        vec![Piece {
+            mr,
+            slot: Slot::Word(0),
+        }]
+    } else if size == word_size {
+        vec![Piece {
+            mr,
+            slot: Slot::Whole,
+        }]
+    } else {
+        vec![Piece {
+            mr,
+            slot: Slot::Bits { offset, bits: size },
+        }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invocations::{Api, ApiElement};
+    use crate::test_support::*;
+
+    fn types(word_size: usize) -> TypeTable {
+        let mut types = TypeTable::new(word_size);
+        types.insert(ParamType::structure("seL4_UserContext", word_size * 3));
+        types
+    }
+
+    fn method(xml: &str) -> Result<Method, TestError> {
+        let api = Api::try_from(roxmltree::Document::parse(xml)?)?;
+        match api.children.into_iter().next() {
+            Some(ApiElement::Interface { mut methods, .. }) => Ok(methods.remove(0)),
+            _ => panic!("expected an interface"),
+        }
+    }
+
+    #[test]
+    fn layout() -> Result<(), TestError> {
+        let method = method(
+            r#"
+<api>
+    <interface name="seL4_TCB">
+        <method id="TCBExample" name="Example">
+            <param dir="in" name="suspend" type="seL4_Bool"/>
+            <param dir="in" name="flags" type="seL4_Uint8"/>
+            <param dir="in" name="root" type="seL4_CNode"/>
+            <param dir="in" name="deadline" type="seL4_Uint64"/>
+            <param dir="in" name="rights" type="seL4_CapRights_t"/>
+            <param dir="in" name="regs" type="seL4_UserContext"/>
+            <param dir="out" name="count" type="seL4_Word"/>
+            <param dir="out" name="enabled" type="seL4_Bool"/>
+        </method>
+    </interface>
+</api>"#,
+        )?;
+
+        let layout = MethodLayout::new(&method, &types(32))?;
+        let pieces: Vec<_> = layout
+            .inputs
+            .iter()
+            .map(|param| (param.name.as_str(), param.pieces.clone()))
+            .collect();
+        let piece = |mr, slot| Piece { mr, slot };
+        assert_eq!(
+            pieces,
+            [
+                ("suspend", vec![piece(0, Slot::Bits { offset: 0, bits: 1 })]),
+                ("flags", vec![piece(0, Slot::Bits { offset: 8, bits: 8 })]),
+                ("root", vec![]),
+                (
+                    "deadline",
+                    vec![
+                        piece(1, Slot::DoubleWord { high: false }),
+                        piece(2, Slot::DoubleWord { high: true })
+                    ]
+                ),
+                ("rights", vec![piece(3, Slot::Word(0))]),
+                (
+                    "regs",
+                    vec![
+                        piece(4, Slot::Word(0)),
+                        piece(5, Slot::Word(1)),
+                        piece(6, Slot::Word(2))
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(layout.extra_caps, ["root"]);
+        assert_eq!(layout.input_length, 7);
+        assert_eq!(layout.input_words()[0].len(), 2);
+        assert_eq!(layout.output_length, 2);
+        assert_eq!(
+            layout.outputs[1].pieces,
+            [piece(1, Slot::Bits { offset: 0, bits: 1 })]
+        );
+        assert!(!layout.exceeds_msg_max_length());
+        assert!(!layout.exceeds_msg_max_extra_caps());
+
+        // 64 bit values fit in a single register.
+        let layout = MethodLayout::new(&method, &types(64))?;
+        assert_eq!(layout.inputs[3].pieces, [piece(1, Slot::Whole)]);
+        assert_eq!(layout.input_length, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn limits() -> Result<(), TestError> {
+        let method = method(
+            r#"
+<api>
+    <interface name="seL4_CNode">
+        <method id="CNodeExample" name="Example">
+            <param dir="in" name="a" type="seL4_CNode"/>
+            <param dir="in" name="b" type="seL4_CNode"/>
+            <param dir="in" name="c" type="seL4_CNode"/>
+            <param dir="in" name="d" type="seL4_CNode"/>
+            <param dir="in" name="e" type="seL4_Thing"/>
+        </method>
+    </interface>
+</api>"#,
+        )?;
+        assert!(matches!(
+            MethodLayout::new(&method, &types(64)),
+            Err(LayoutError::Type(TypeError::UnknownType { ref param, .. })) if param == "e"
+        ));
+
+        let mut types = types(64);
+        types.insert(ParamType::structure("seL4_Thing", 64 * 121));
+        let layout = MethodLayout::new(&method, &types)?;
+        assert!(layout.exceeds_msg_max_extra_caps());
+        assert!(layout.exceeds_msg_max_length());
+        Ok(())
+    }
+}
@@ -1,4 +1,18 @@
+pub mod arch;
+pub mod borrowed;
+pub mod builder;
+pub mod condition;
+pub mod config;
+pub mod diagnostic;
+pub mod error;
 mod invocation_helper;
+pub mod invocation_table;
 pub mod invocations;
+pub mod kernel_tree;
+pub mod layout;
 pub mod syscalls;
 mod test_support;
+pub mod types;
+pub mod validate;
+pub mod writer;
+pub mod xref;
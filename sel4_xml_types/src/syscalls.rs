@@ -1,4 +1,14 @@
+use crate::config::{ConfigError, KernelConfig};
+use crate::error::Sel4XmlError;
+use crate::invocation_helper::*;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+//
+// syscall.xml is read with roxmltree like the IDL files, rather than
+// deserialized with serde, so that its errors are located in the same way.
+// The types still implement `Deserialize` for other uses.
+//
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -16,8 +26,12 @@ pub struct Api {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub condition: Option<String>,
-    #[serde(rename="syscall")]
+    #[serde(rename = "syscall")]
     pub syscalls: Vec<Syscall>,
+    /// Set by `configure` when the condition does not hold. The syscalls
+    /// still take up their numbers, but are left out of `numbered`.
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -25,28 +39,259 @@ pub struct Syscall {
     pub name: String,
 }
 
+impl Config {
+    fn from_node(node: roxmltree::Node<'_, '_>) -> Result<Config, Sel4XmlError> {
+        if node.tag_name().name() != "config" {
+            return Err(unsupported_node(node));
+        }
+        let syscalls = elements(node)?
+            .into_iter()
+            .map(Syscall::from_node)
+            .collect::<Result<Vec<_>, _>>()?;
+        if syscalls.is_empty() {
+            return Err(Sel4XmlError::Element("syscall".to_string(), location(node)));
+        }
+        Ok(Config {
+            condition: node.opt_attr("condition").map(str::to_string),
+            syscalls,
+            disabled: false,
+        })
+    }
+}
+
+impl Syscall {
+    fn from_node(node: roxmltree::Node<'_, '_>) -> Result<Syscall, Sel4XmlError> {
+        if node.tag_name().name() != "syscall" {
+            return Err(unsupported_node(node));
+        }
+        Ok(Syscall {
+            name: node.req_attr("name")?.to_string(),
+        })
+    }
+}
+
+// The child elements of `node`, which may have nothing else but
+// whitespace and comments.
+fn elements<'a, 'b>(
+    node: roxmltree::Node<'a, 'b>,
+) -> Result<Vec<roxmltree::Node<'a, 'b>>, Sel4XmlError> {
+    let mut elements = Vec::new();
+    for child in node.children() {
+        if child.is_element() {
+            elements.push(child);
+        } else if !is_whitespace(child) && !child.is_comment() {
+            return Err(unsupported_node(child));
+        }
+    }
+    Ok(elements)
+}
+
+fn child_element<'a, 'b>(
+    node: roxmltree::Node<'a, 'b>,
+    name: &str,
+) -> Result<roxmltree::Node<'a, 'b>, Sel4XmlError> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+        .ok_or_else(|| Sel4XmlError::Element(name.to_string(), location(node)))
+}
+
+/// A syscall with the number it is assigned in the kernel ABI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NumberedSyscall {
+    pub name: String,
+    pub number: isize,
+    /// The condition of the `<config>` the syscall was declared in.
+    pub condition: Option<String>,
+}
+
+impl Syscalls {
+    /// Parses the syscalls in `text`, errors refer to it as `file`.
+    pub fn parse(file: &Path, text: &str) -> Result<Syscalls, Sel4XmlError> {
+        let doc = roxmltree::Document::parse(text)
+            .map_err(|err| Sel4XmlError::Xml(file.to_path_buf(), err))?;
+        Syscalls::try_from(doc).map_err(|err| err.in_file(file))
+    }
+
+    /// The api syscalls for either the MCS or the master kernel.
+    pub fn api(&self, mcs: bool) -> &Api {
+        if mcs {
+            &self.api_mcs
+        } else {
+            &self.api_master
+        }
+    }
+
+    /// Numbers the syscalls in declaration order, counting down from `-1`.
+    ///
+    /// The api syscalls come first, and if `include_debug` is set
+    /// the debug syscalls are numbered after them.
+    pub fn numbered(&self, mcs: bool, include_debug: bool) -> Vec<NumberedSyscall> {
+        let debug = if include_debug {
+            self.debug.config.as_slice()
+        } else {
+            &[]
+        };
+        self.api(mcs)
+            .config
+            .iter()
+            .chain(debug)
+            .flat_map(|config| config.syscalls.iter().map(move |syscall| (config, syscall)))
+            .enumerate()
+            .filter(|(_, (config, _))| !config.disabled)
+            .map(|(i, (config, syscall))| NumberedSyscall {
+                name: syscall.name.clone(),
+                number: -(i as isize) - 1,
+                condition: config.condition.clone(),
+            })
+            .collect()
+    }
+
+    /// The lowest numbered api syscall, which is `SYSCALL_MIN` in the kernel.
+    /// Debug syscalls are numbered below this.
+    pub fn syscall_min(&self, mcs: bool) -> isize {
+        let count: usize = self
+            .api(mcs)
+            .config
+            .iter()
+            .map(|config| config.syscalls.len())
+            .sum();
+        -(count as isize)
+    }
+
+    /// Disables the syscalls whose condition does not hold in `config`,
+    /// the remaining ones are left unconditional. Every syscall keeps its
+    /// number, as the kernel ABI numbers them whether they exist or not.
+    pub fn configure(&self, config: &KernelConfig) -> Result<Syscalls, ConfigError> {
+        Ok(Syscalls {
+            api_master: self.api_master.configure(config)?,
+            api_mcs: self.api_mcs.configure(config)?,
+            debug: self.debug.configure(config)?,
+        })
+    }
+}
+
+impl<'a> TryFrom<roxmltree::Document<'a>> for Syscalls {
+    type Error = Sel4XmlError;
+    fn try_from(doc: roxmltree::Document<'a>) -> Result<Syscalls, Sel4XmlError> {
+        let node = doc.root_element();
+        if node.tag_name().name() != "syscalls" {
+            return Err(unsupported_node(node));
+        }
+        for child in elements(node)? {
+            if !["api-master", "api-mcs", "debug"].contains(&child.tag_name().name()) {
+                return Err(unsupported_node(child));
+            }
+        }
+        Ok(Syscalls {
+            api_master: Api::from_node(child_element(node, "api-master")?)?,
+            api_mcs: Api::from_node(child_element(node, "api-mcs")?)?,
+            debug: Api::from_node(child_element(node, "debug")?)?,
+        })
+    }
+}
+
+impl Api {
+    fn from_node(node: roxmltree::Node<'_, '_>) -> Result<Api, Sel4XmlError> {
+        let config = elements(node)?
+            .into_iter()
+            .map(Config::from_node)
+            .collect::<Result<Vec<_>, _>>()?;
+        if config.is_empty() {
+            return Err(Sel4XmlError::Element("config".to_string(), location(node)));
+        }
+        Ok(Api { config })
+    }
+
+    pub fn configure(&self, config: &KernelConfig) -> Result<Api, ConfigError> {
+        let mut configs = Vec::new();
+        for c in &self.config {
+            configs.push(Config {
+                condition: None,
+                syscalls: c.syscalls.clone(),
+                disabled: c.disabled || !config.holds(c.condition.as_deref())?,
+            });
+        }
+        Ok(Api { config: configs })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_support::*;
-    use std::fs::File;
-    use std::io::BufReader;
+    use std::fs;
+
+    const SYSCALL_XML: &str = "../../../kernel/libsel4/include/api/syscall.xml";
 
     #[test]
-    fn quick_xml_works() -> Result<(), TestError> {
-        let f = File::open("../../../kernel/libsel4/include/api/syscall.xml")?;
-        let reader = BufReader::new(f);
-        let r: Syscalls = quick_xml::de::from_reader(reader)?;
-        println!("{:#?}", r);
+    fn numbered() -> Result<(), TestError> {
+        let syscalls = Syscalls::parse(
+            Path::new("syscall.xml"),
+            r#"
+<syscalls>
+    <api-master>
+        <config><syscall name="Call"/><syscall name="ReplyRecv"/></config>
+        <config><syscall name="Yield"/></config>
+    </api-master>
+    <api-mcs><config><syscall name="Call"/></config></api-mcs>
+    <debug>
+        <config condition="defined CONFIG_PRINTING"><syscall name="DebugPutChar"/></config>
+    </debug>
+</syscalls>"#,
+        )?;
+        let numbered = syscalls.numbered(false, true);
+        let numbers: Vec<_> = numbered
+            .iter()
+            .map(|syscall| (syscall.name.as_str(), syscall.number))
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                ("Call", -1),
+                ("ReplyRecv", -2),
+                ("Yield", -3),
+                ("DebugPutChar", -4)
+            ]
+        );
+        assert_eq!(
+            numbered[3].condition.as_deref(),
+            Some("defined CONFIG_PRINTING")
+        );
+        assert_eq!(syscalls.syscall_min(false), -3);
+        assert_eq!(syscalls.numbered(true, false).len(), 1);
+        assert_eq!(syscalls.syscall_min(true), -1);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<(), TestError> {
+        let xml = r#"<syscalls>
+    <api-master><config><syscall name="Call"/></config></api-master>
+    <api-mcs>
+        <config><syscal name="Call"/></config>
+    </api-mcs>
+</syscalls>"#;
+        let err = Syscalls::parse(Path::new("syscall.xml"), xml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "syscall.xml:4:17 api-mcs[1]/config[1]/syscal[1]: \
+             cannot convert element from unknown element 'syscal'"
+        );
+
+        let xml = xml.replace("syscal ", "syscall ");
+        let err = Syscalls::parse(Path::new("syscall.xml"), &xml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "syscall.xml:1:1 syscalls: element 'debug' not found"
+        );
         Ok(())
     }
 
     #[test]
-    fn serde_xml_works() -> Result<(), TestError> {
-        let f = File::open("../../../kernel/libsel4/include/api/syscall.xml")?;
-        let reader = BufReader::new(f);
-        let r: Syscalls = serde_xml_rs::de::from_reader(reader)?;
-        println!("{:#?}", r);
+    fn kernel_syscall_xml() -> Result<(), TestError> {
+        let syscalls = Syscalls::parse(Path::new(SYSCALL_XML), &fs::read_to_string(SYSCALL_XML)?)?;
+        assert!(!syscalls.numbered(false, true).is_empty());
+        assert!(!syscalls.numbered(true, true).is_empty());
         Ok(())
     }
 }
@@ -4,14 +4,20 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum TestError {
-    #[error("generating invocations")]
-    InvocationGen(#[from] crate::invocations::InvocationGenError),
-    #[error("quick_xml deserialization failed")]
-    Deserialization(#[from] quick_xml::DeError),
-    #[error("xml-rs deserialization failed")]
-    XMLRs(#[from] serde_xml_rs::Error),
+    #[error("parsing the xml")]
+    Sel4Xml(#[from] crate::error::Sel4XmlError),
     #[error("roxml parsing failed")]
     Roxmltree(#[from] roxmltree::Error),
+    #[error("kernel configuration")]
+    Config(#[from] crate::config::ConfigError),
+    #[error("message layout")]
+    Layout(#[from] crate::layout::LayoutError),
+    #[error("param types")]
+    Type(#[from] crate::types::TypeError),
+    #[error("building the IDL")]
+    Build(#[from] crate::builder::BuildError),
+    #[error("kernel tree")]
+    KernelTree(#[from] crate::kernel_tree::KernelTreeError),
     #[error("filesystem error")]
     Filesystem(#[from] std::io::Error),
 }
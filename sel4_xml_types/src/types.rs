@@ -0,0 +1,271 @@
+use crate::invocations::{Api, ApiElement, Method, Param};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+//
+// The resolved types of invocation params, after the type table of
+// syscall_stub_gen.py.
+//
+// `Param` only knows the name of its type. Generators need to know how
+// many bits a value occupies and how it travels: caps as extra caps, words
+// and bitfields packed into message registers, `<struct>`s member by member.
+//
+
+#[derive(Error, Debug)]
+pub enum TypeError {
+    #[error("unknown type '{typ}' for param '{param}' of method '{method}'")]
+    UnknownType {
+        method: String,
+        param: String,
+        typ: String,
+    },
+    #[error("struct '{name}' declares {members} members, too few for {size_bits} bits")]
+    StructMembers {
+        name: String,
+        members: usize,
+        size_bits: usize,
+    },
+}
+
+/// How a value of the type is moved into and out of message registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypeKind {
+    /// Plain integers, packed into words by their size.
+    Word,
+    /// Capabilities travel as extra caps rather than in message registers.
+    Cap,
+    /// Bitfield structs e.g. `seL4_CapRights_t`, made of `words`.
+    BitField,
+    /// Structs declared by `<struct>` in the IDL, one word per member.
+    Struct,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamType {
+    pub name: String,
+    /// Size within the message.
+    pub size_bits: usize,
+    /// Size as the C compiler sees it.
+    pub native_size_bits: usize,
+    /// 64 bit values split across two registers on 32 bit architectures.
+    pub double_word: bool,
+    pub kind: TypeKind,
+    /// The members of a `<struct>`, one per word.
+    pub members: Vec<String>,
+}
+
+impl ParamType {
+    pub fn new(name: &str, size_bits: usize, kind: TypeKind) -> ParamType {
+        ParamType {
+            name: name.to_string(),
+            size_bits,
+            native_size_bits: size_bits,
+            double_word: false,
+            kind,
+            members: Vec::new(),
+        }
+    }
+
+    pub fn word(name: &str, size_bits: usize) -> ParamType {
+        ParamType::new(name, size_bits, TypeKind::Word)
+    }
+
+    /// A capability, which is a word sized `seL4_CPtr` to the C compiler.
+    pub fn cap(name: &str, word_size: usize) -> ParamType {
+        ParamType::new(name, word_size, TypeKind::Cap)
+    }
+
+    pub fn bitfield(name: &str, size_bits: usize) -> ParamType {
+        ParamType::new(name, size_bits, TypeKind::BitField)
+    }
+
+    /// A struct whose members are given by a `<struct>` declaration.
+    pub fn structure(name: &str, size_bits: usize) -> ParamType {
+        ParamType::new(name, size_bits, TypeKind::Struct)
+    }
+
+    /// Types larger than a word which aren't split across two registers
+    /// are passed to stubs by pointer.
+    pub fn pass_by_reference(&self, word_size: usize) -> bool {
+        self.size_bits > word_size && !self.double_word
+    }
+}
+
+/// The types known for one word size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeTable {
+    word_size: usize,
+    types: Vec<ParamType>,
+}
+
+impl TypeTable {
+    /// The types every architecture shares.
+    pub fn new(word_size: usize) -> TypeTable {
+        let cap = |name: &str| ParamType::cap(name, word_size);
+        TypeTable {
+            word_size,
+            types: vec![
+                ParamType::word("int", 32),
+                ParamType::word("long", word_size),
+                ParamType::word("seL4_Uint8", 8),
+                ParamType::word("seL4_Uint16", 16),
+                ParamType::word("seL4_Uint32", 32),
+                ParamType {
+                    double_word: word_size == 32,
+                    ..ParamType::word("seL4_Uint64", 64)
+                },
+                ParamType {
+                    double_word: word_size == 32,
+                    ..ParamType::word("seL4_Time", 64)
+                },
+                ParamType::word("seL4_Word", word_size),
+                ParamType {
+                    native_size_bits: 8,
+                    ..ParamType::word("seL4_Bool", 1)
+                },
+                ParamType::bitfield("seL4_CapRights_t", word_size),
+                ParamType::bitfield("seL4_MessageInfo_t", word_size),
+                cap("seL4_CPtr"),
+                cap("seL4_CNode"),
+                cap("seL4_IRQHandler"),
+                cap("seL4_IRQControl"),
+                cap("seL4_TCB"),
+                cap("seL4_Untyped"),
+                cap("seL4_DomainSet"),
+                cap("seL4_SchedContext"),
+                cap("seL4_SchedControl"),
+            ],
+        }
+    }
+
+    pub fn word_size(&self) -> usize {
+        self.word_size
+    }
+
+    /// Adds `typ`, replacing any type of the same name.
+    pub fn insert(&mut self, typ: ParamType) {
+        match self.types.iter_mut().find(|known| known.name == typ.name) {
+            Some(known) => *known = typ,
+            None => self.types.push(typ),
+        }
+    }
+
+    /// Records the members of the `<struct>`s declared by `api`.
+    ///
+    /// Structs the table doesn't know yet are one word per member, known
+    /// structs must have a member for each of their words.
+    pub fn declare_structs(&mut self, api: &Api) -> Result<(), TypeError> {
+        for child in &api.children {
+            if let ApiElement::StructElem { name, members, .. } = child {
+                let size_bits = match self.get(name) {
+                    Some(known) => known.size_bits,
+                    None => members.len() * self.word_size,
+                };
+                if members.len() * self.word_size < size_bits {
+                    return Err(TypeError::StructMembers {
+                        name: name.clone(),
+                        members: members.len(),
+                        size_bits,
+                    });
+                }
+                self.insert(ParamType {
+                    members: members.clone(),
+                    ..ParamType::structure(name, size_bits)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParamType> {
+        self.types.iter().find(|typ| typ.name == name)
+    }
+
+    /// The type of `param` of `method`.
+    pub fn resolve(&self, method: &Method, param: &Param) -> Result<&ParamType, TypeError> {
+        self.get(param.typ()).ok_or_else(|| TypeError::UnknownType {
+            method: method.id.clone(),
+            param: param.name().to_string(),
+            typ: param.typ().to_string(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ParamType> {
+        self.types.iter()
+    }
+}
+
+impl Extend<ParamType> for TypeTable {
+    fn extend<I: IntoIterator<Item = ParamType>>(&mut self, types: I) {
+        for typ in types {
+            self.insert(typ);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    const API: &str = r#"
+<api>
+    <struct name="seL4_Pair">
+        <member name="first"/>
+        <member name="second"/>
+    </struct>
+    <interface name="seL4_TCB">
+        <method id="TCBExample" name="Example">
+            <param dir="in" name="pair" type="seL4_Pair"/>
+            <param dir="in" name="rights" type="seL4_CapRights_t"/>
+            <param dir="in" name="root" type="seL4_CNode"/>
+            <param dir="in" name="thing" type="seL4_Thing"/>
+        </method>
+    </interface>
+</api>"#;
+
+    #[test]
+    fn resolve() -> Result<(), TestError> {
+        let api = Api::try_from(roxmltree::Document::parse(API)?)?;
+        let mut types = TypeTable::new(64);
+        types.declare_structs(&api)?;
+
+        let method = match &api.children[1] {
+            ApiElement::Interface { methods, .. } => &methods[0],
+            _ => panic!("expected an interface"),
+        };
+        let kinds = method.params[..3]
+            .iter()
+            .map(|param| Ok(types.resolve(method, param)?.kind))
+            .collect::<Result<Vec<_>, TypeError>>()?;
+        assert_eq!(kinds, [TypeKind::Struct, TypeKind::BitField, TypeKind::Cap]);
+
+        let pair = types.get("seL4_Pair").expect("declared struct");
+        assert_eq!(pair.size_bits, 128);
+        assert_eq!(pair.members, ["first", "second"]);
+        assert!(pair.pass_by_reference(64));
+
+        assert!(matches!(
+            types.resolve(method, &method.params[3]),
+            Err(TypeError::UnknownType { ref method, ref param, .. })
+                if method == "TCBExample" && param == "thing"
+        ));
+
+        // 64 bit values take two registers on 32 bit architectures.
+        let types = TypeTable::new(32);
+        assert!(types.get("seL4_Uint64").is_some_and(|typ| typ.double_word));
+        Ok(())
+    }
+
+    #[test]
+    fn struct_members() -> Result<(), TestError> {
+        let api = Api::try_from(roxmltree::Document::parse(API)?)?;
+        let mut types = TypeTable::new(32);
+        types.insert(ParamType::structure("seL4_Pair", 32 * 3));
+        assert!(matches!(
+            types.declare_structs(&api),
+            Err(TypeError::StructMembers { members: 2, .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+use crate::invocation_helper::{is_whitespace, location};
+use crate::invocations::Location;
+use std::path::Path;
+use thiserror::Error;
+
+//
+// Checks IDL files against the content model of sel4_idl.dtd.
+//
+// The parser in invocations.rs is lenient, it skips children it doesn't
+// expect and ignores attributes it doesn't know. Validation catches
+// such mistakes before they turn into missing stubs or C compiler errors.
+//
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("{1}: element `{0}` is not allowed in `{2}`")]
+    UnknownElement(String, Location, String),
+    #[error("{1}: element `{0}` is out of order, expected {2}")]
+    Order(String, Location, String),
+    #[error("{1}: required attribute `{0}` is missing")]
+    MissingAttribute(String, Location),
+    #[error("{1}: attribute `{0}` is not allowed on `{2}`")]
+    UnknownAttribute(String, Location, String),
+    #[error("{1}: `{0}` must be {2}")]
+    Value(String, Location, String),
+    #[error("{0}: text is not allowed in `{1}`")]
+    Text(Location, String),
+}
+
+impl ValidationError {
+    pub fn location(&self) -> &Location {
+        match self {
+            ValidationError::UnknownElement(_, location, _)
+            | ValidationError::Order(_, location, _)
+            | ValidationError::MissingAttribute(_, location)
+            | ValidationError::UnknownAttribute(_, location, _)
+            | ValidationError::Value(_, location, _)
+            | ValidationError::Text(location, _) => location,
+        }
+    }
+
+    fn in_file(self, file: &Path) -> ValidationError {
+        match self {
+            ValidationError::UnknownElement(name, location, parent) => {
+                ValidationError::UnknownElement(name, location.in_file(file), parent)
+            }
+            ValidationError::Order(name, location, expected) => {
+                ValidationError::Order(name, location.in_file(file), expected)
+            }
+            ValidationError::MissingAttribute(attr, location) => {
+                ValidationError::MissingAttribute(attr, location.in_file(file))
+            }
+            ValidationError::UnknownAttribute(attr, location, element) => {
+                ValidationError::UnknownAttribute(attr, location.in_file(file), element)
+            }
+            ValidationError::Value(attr, location, values) => {
+                ValidationError::Value(attr, location.in_file(file), values)
+            }
+            ValidationError::Text(location, element) => {
+                ValidationError::Text(location.in_file(file), element)
+            }
+        }
+    }
+}
+
+/// The attributes sel4_idl.dtd declares for `element`.
+pub fn attributes(element: &str) -> Vec<&'static str> {
+    ELEMENTS
+        .iter()
+        .filter(|known| known.name == element)
+        .flat_map(|known| known.attributes.iter().map(|attr| attr.name))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Occurs {
+    /// `?`
+    Optional,
+    /// `*`
+    Any,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Content {
+    /// `EMPTY`
+    Empty,
+    /// `(a?, b*, ...)`
+    Sequence(&'static [(&'static str, Occurs)]),
+    /// `(a|b)*`
+    Choice(&'static [&'static str]),
+    /// `(#PCDATA|a|b)*`
+    Mixed(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attribute {
+    name: &'static str,
+    required: bool,
+    /// The enumerated values, any text if `None`.
+    values: Option<&'static [&'static str]>,
+}
+
+const fn required(name: &'static str) -> Attribute {
+    Attribute {
+        name,
+        required: true,
+        values: None,
+    }
+}
+
+const fn implied(name: &'static str) -> Attribute {
+    Attribute {
+        name,
+        required: false,
+        values: None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Element {
+    name: &'static str,
+    attributes: &'static [Attribute],
+    content: Content,
+}
+
+const DOC_LEAVES: &[&str] = &["texttt", "docref", "shortref", "autoref", "obj"];
+
+// sel4_idl.dtd
+const ELEMENTS: &[Element] = &[
+    Element {
+        name: "api",
+        attributes: &[implied("name"), implied("label_prefix")],
+        content: Content::Choice(&["struct", "interface"]),
+    },
+    Element {
+        name: "struct",
+        attributes: &[required("name")],
+        content: Content::Sequence(&[("member", Occurs::Any)]),
+    },
+    Element {
+        name: "member",
+        attributes: &[required("name")],
+        content: Content::Empty,
+    },
+    Element {
+        name: "interface",
+        attributes: &[
+            required("name"),
+            implied("manual_name"),
+            implied("cap_description"),
+        ],
+        content: Content::Sequence(&[("method", Occurs::Any)]),
+    },
+    Element {
+        name: "method",
+        attributes: &[
+            required("id"),
+            required("name"),
+            implied("condition"),
+            implied("manual_name"),
+            implied("manual_label"),
+        ],
+        content: Content::Sequence(&[
+            ("brief", Occurs::Optional),
+            ("description", Occurs::Optional),
+            ("return", Occurs::Optional),
+            ("cap_param", Occurs::Optional),
+            ("param", Occurs::Any),
+            ("error", Occurs::Any),
+        ]),
+    },
+    Element {
+        name: "brief",
+        attributes: &[],
+        content: Content::Mixed(DOC_LEAVES),
+    },
+    Element {
+        name: "description",
+        attributes: &[],
+        content: Content::Mixed(DOC_LEAVES),
+    },
+    Element {
+        name: "return",
+        attributes: &[],
+        content: Content::Mixed(&[
+            "errorenumdesc",
+            "texttt",
+            "docref",
+            "shortref",
+            "autoref",
+            "obj",
+        ]),
+    },
+    Element {
+        name: "errorenumdesc",
+        attributes: &[],
+        content: Content::Empty,
+    },
+    Element {
+        name: "cap_param",
+        attributes: &[required("append_description")],
+        content: Content::Empty,
+    },
+    Element {
+        name: "param",
+        attributes: &[
+            required("type"),
+            required("name"),
+            Attribute {
+                name: "dir",
+                required: true,
+                values: Some(&["in", "out"]),
+            },
+            implied("description"),
+        ],
+        content: Content::Sequence(&[("description", Occurs::Optional), ("error", Occurs::Any)]),
+    },
+    Element {
+        name: "error",
+        attributes: &[required("name"), implied("description")],
+        content: Content::Sequence(&[("description", Occurs::Optional)]),
+    },
+    Element {
+        name: "docref",
+        attributes: &[],
+        content: Content::Mixed(&["texttt", "shortref", "autoref", "obj"]),
+    },
+    Element {
+        name: "texttt",
+        attributes: &[required("text")],
+        content: Content::Empty,
+    },
+    Element {
+        name: "shortref",
+        attributes: &[required("sec")],
+        content: Content::Empty,
+    },
+    Element {
+        name: "autoref",
+        attributes: &[required("label")],
+        content: Content::Empty,
+    },
+    Element {
+        name: "obj",
+        attributes: &[required("name")],
+        content: Content::Empty,
+    },
+];
+
+/// Every way `doc` departs from sel4_idl.dtd, in document order.
+///
+/// `file` is recorded in the locations of the errors.
+pub fn validate(doc: &roxmltree::Document<'_>, file: Option<&Path>) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let root = doc.root_element();
+    if root.tag_name().name() == "api" {
+        validate_element(root, &ELEMENTS[0], &mut errors);
+    } else {
+        errors.push(ValidationError::UnknownElement(
+            root.tag_name().name().to_string(),
+            location(root),
+            "the document".to_string(),
+        ));
+    }
+    match file {
+        Some(file) => errors.into_iter().map(|err| err.in_file(file)).collect(),
+        None => errors,
+    }
+}
+
+fn validate_element(
+    node: roxmltree::Node<'_, '_>,
+    element: &Element,
+    errors: &mut Vec<ValidationError>,
+) {
+    for attr in node.attributes() {
+        let attr_location = || Location {
+            span: attr.range(),
+            ..location(node)
+        };
+        match element
+            .attributes
+            .iter()
+            .find(|known| known.name == attr.name())
+        {
+            None => errors.push(ValidationError::UnknownAttribute(
+                attr.name().to_string(),
+                attr_location(),
+                element.name.to_string(),
+            )),
+            Some(Attribute {
+                values: Some(values),
+                ..
+            }) if !values.contains(&attr.value()) => errors.push(ValidationError::Value(
+                attr.name().to_string(),
+                attr_location(),
+                expected(values),
+            )),
+            Some(_) => {}
+        }
+    }
+    for attr in element.attributes {
+        if attr.required && node.attribute(attr.name).is_none() {
+            errors.push(ValidationError::MissingAttribute(
+                attr.name.to_string(),
+                location(node),
+            ));
+        }
+    }
+
+    // Where in a `Content::Sequence` the previous child matched.
+    let mut next = 0;
+    for child in node.children() {
+        if child.is_comment() || child.is_pi() || is_whitespace(child) {
+            continue;
+        }
+        if child.is_text() {
+            if !matches!(element.content, Content::Mixed(_)) {
+                errors.push(ValidationError::Text(
+                    location(child),
+                    element.name.to_string(),
+                ));
+            }
+            continue;
+        }
+
+        let name = child.tag_name().name();
+        let allowed = match element.content {
+            Content::Empty => false,
+            Content::Choice(names) | Content::Mixed(names) => names.contains(&name),
+            Content::Sequence(sequence) => {
+                match sequence[next..]
+                    .iter()
+                    .position(|(known, _)| *known == name)
+                {
+                    Some(skipped) => {
+                        next += skipped;
+                        if sequence[next].1 == Occurs::Optional {
+                            next += 1;
+                        }
+                        true
+                    }
+                    None if sequence.iter().any(|(known, _)| *known == name) => {
+                        let rest: Vec<&str> =
+                            sequence[next..].iter().map(|(known, _)| *known).collect();
+                        errors.push(ValidationError::Order(
+                            name.to_string(),
+                            location(child),
+                            expected(&rest),
+                        ));
+                        // Its own content can still be checked.
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+        if !allowed {
+            errors.push(ValidationError::UnknownElement(
+                name.to_string(),
+                location(child),
+                element.name.to_string(),
+            ));
+            continue;
+        }
+        if let Some(child_element) = ELEMENTS.iter().find(|known| known.name == name) {
+            validate_element(child, child_element, errors);
+        }
+    }
+}
+
+// e.g. "one of `in`, `out`" or "nothing more"
+fn expected(names: &[&str]) -> String {
+    match names {
+        [] => "nothing more".to_string(),
+        [name] => format!("`{}`", name),
+        names => {
+            let names: Vec<String> = names.iter().map(|name| format!("`{}`", name)).collect();
+            format!("one of {}", names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn valid() -> Result<(), TestError> {
+        let xml = r#"<?xml version="1.0" ?>
+<api name="ObjectApi" label_prefix="seL4_">
+    <!-- A comment -->
+    <struct name="seL4_Pair">
+        <member name="first"/>
+    </struct>
+    <interface name="seL4_TCB" manual_name="TCB" cap_description="Capability to the TCB">
+        <method id="TCBSuspend" name="Suspend" condition="defined(CONFIG_X)">
+            <brief>Suspend a <texttt text="tcb"/></brief>
+            <description><docref>See <shortref sec="threads"/></docref></description>
+            <return><errorenumdesc/></return>
+            <cap_param append_description="The thread"/>
+            <param dir="in" name="a" type="seL4_Word" description="A"/>
+            <param dir="out" name="b" type="seL4_Word">
+                <description>B</description>
+                <error name="seL4_InvalidArgument"/>
+            </param>
+            <error name="seL4_IllegalOperation" description="Always"/>
+        </method>
+    </interface>
+</api>"#;
+        let doc = roxmltree::Document::parse(xml)?;
+        assert_eq!(validate(&doc, None), []);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSuspend" name="Suspend" colour="red">
+            <param dir="inout" name="a" type="seL4_Word"/>
+            <brief>Suspend</brief>
+            <param name="b" type="seL4_Word"/>
+            <parm dir="in" name="c" type="seL4_Word"/>
+            stray text
+        </method>
+    </interface>
+</api>"#;
+        let doc = roxmltree::Document::parse(xml)?;
+        let errors = validate(&doc, Some(Path::new("sel4.xml")));
+        let summary: Vec<(String, u32)> = errors
+            .iter()
+            .map(|err| {
+                let message = err.to_string();
+                let message = message.split_once(": ").unwrap().1.to_string();
+                (message, err.location().position.line)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "attribute `colour` is not allowed on `method`".to_string(),
+                    3
+                ),
+                ("`dir` must be one of `in`, `out`".to_string(), 4),
+                (
+                    "element `brief` is out of order, expected one of `param`, `error`".to_string(),
+                    5
+                ),
+                ("required attribute `dir` is missing".to_string(), 6),
+                ("element `parm` is not allowed in `method`".to_string(), 7),
+                ("text is not allowed in `method`".to_string(), 7),
+            ]
+        );
+        assert!(errors[0].to_string().starts_with("sel4.xml:3:9 "));
+        assert_eq!(&xml[errors[0].location().span.clone()], "colour=\"red\"");
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+use crate::invocations::*;
+
+//
+// Writes an `Api` back as sel4_idl.dtd xml.
+//
+// Parsed elements keep their `Formatting`, the attributes as written and
+// the whitespace and comments between their children. Writing an unchanged
+// `Api` gives back the parsed xml, so scripts can edit the IDL and leave
+// a diff of just their changes. Changed attribute values are written
+// afresh, new elements are indented by `INDENT` per level.
+//
+// Text is kept as parsed, but entities other than `&amp;` and `&lt;`
+// are written as the characters they stand for.
+//
+
+const INDENT: &str = "    ";
+
+impl Api {
+    /// The xml of the api, as it was parsed unless it has been changed since.
+    pub fn to_xml(&self) -> String {
+        let mut writer = Writer::default();
+        let parsed = !self.formatting.tag_end.is_empty();
+        if parsed {
+            writer.out.push_str(&self.formatting.prolog);
+        } else {
+            writer.out.push_str("<?xml version=\"1.0\" ?>\n");
+        }
+        let children = self.children.iter().map(Child::ApiElement).collect();
+        writer.element(
+            "api",
+            &[
+                ("name", self.name.as_deref()),
+                ("label_prefix", self.label_prefix.as_deref()),
+            ],
+            &self.formatting,
+            children,
+        );
+        if parsed {
+            writer.out.push_str(&self.formatting.epilogue);
+        } else {
+            writer.out.push('\n');
+        }
+        writer.out
+    }
+}
+
+// The child elements of the elements of the IDL.
+enum Child<'a> {
+    ApiElement(&'a ApiElement),
+    Member(&'a str),
+    Method(&'a Method),
+    /// `<brief>` or `<description>`
+    Leaves(&'static str, &'a [DocLeaf]),
+    Return(&'a [Return]),
+    CapParam(&'a CapParam),
+    Param(&'a Param),
+    Error(&'a ErrorElement),
+}
+
+impl Child<'_> {
+    // The key of the gap written before the child.
+    fn gap_key(&self) -> String {
+        let (tag, name) = match self {
+            Child::ApiElement(ApiElement::StructElem { name, .. }) => {
+                ("struct", Some(name.as_str()))
+            }
+            Child::ApiElement(ApiElement::Interface { name, .. }) => {
+                ("interface", Some(name.as_str()))
+            }
+            Child::Member(name) => ("member", Some(*name)),
+            Child::Method(method) => ("method", Some(method.id.as_str())),
+            Child::Leaves(tag, _) => (*tag, None),
+            Child::Return(_) => ("return", None),
+            Child::CapParam(_) => ("cap_param", None),
+            Child::Param(param) => ("param", Some(param.name())),
+            Child::Error(error) => ("error", Some(error.name())),
+        };
+        gap_key(tag, name)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    depth: usize,
+}
+
+impl Writer {
+    fn element(
+        &mut self,
+        tag: &str,
+        attributes: &[(&str, Option<&str>)],
+        formatting: &Formatting,
+        children: Vec<Child<'_>>,
+    ) {
+        self.out.push('<');
+        self.out.push_str(tag);
+        for raw in &formatting.attributes {
+            match attributes.iter().find(|(name, _)| *name == raw.name) {
+                Some((_, Some(value))) if *value == raw.value => {
+                    self.out.push_str(&raw.before);
+                    self.out.push_str(&raw.raw);
+                }
+                Some((name, Some(value))) => {
+                    self.out.push_str(&raw.before);
+                    self.attribute(name, value);
+                }
+                // Removed since it was parsed.
+                Some((_, None)) => {}
+                // Not in the model, kept as it is.
+                None => {
+                    self.out.push_str(&raw.before);
+                    self.out.push_str(&raw.raw);
+                }
+            }
+        }
+        for (name, value) in attributes {
+            let written = formatting.attributes.iter().any(|raw| raw.name == *name);
+            if let (Some(value), false) = (value, written) {
+                self.out.push(' ');
+                self.attribute(name, value);
+            }
+        }
+
+        // Whatever whitespace came before `>` or `/>`
+        self.out.push_str(
+            formatting
+                .tag_end
+                .trim_end_matches('>')
+                .trim_end_matches('/'),
+        );
+        let self_closing = formatting.tag_end.is_empty() || formatting.tag_end.ends_with("/>");
+        if children.is_empty() && self_closing {
+            self.out.push_str("/>");
+            return;
+        }
+        self.out.push('>');
+
+        let new_line = |depth: usize| format!("\n{}", INDENT.repeat(depth));
+        self.depth += 1;
+        // The gaps of removed children are left out, in order for children
+        // with the same key.
+        let mut gaps: Vec<_> = formatting.gaps.iter().collect();
+        for child in &children {
+            let key = child.gap_key();
+            match gaps.iter().position(|(gap_key, _)| *gap_key == key) {
+                Some(i) => self.out.push_str(&gaps.remove(i).1),
+                None => self.out.push_str(&new_line(self.depth)),
+            }
+            self.child(child);
+        }
+        self.depth -= 1;
+
+        if formatting.gaps.is_empty() && !children.is_empty() {
+            self.out.push_str(&new_line(self.depth));
+        } else {
+            self.out.push_str(&formatting.end_gap);
+        }
+        self.out.push_str("</");
+        self.out.push_str(tag);
+        self.out.push('>');
+    }
+
+    fn child(&mut self, child: &Child<'_>) {
+        match child {
+            Child::ApiElement(ApiElement::StructElem {
+                name,
+                members,
+                formatting,
+            }) => {
+                let members = members.iter().map(|name| Child::Member(name)).collect();
+                self.element("struct", &[("name", Some(name))], formatting, members);
+            }
+            Child::ApiElement(ApiElement::Interface {
+                name,
+                manual_name,
+                cap_desc,
+                methods,
+                formatting,
+            }) => {
+                let methods = methods.iter().map(Child::Method).collect();
+                self.element(
+                    "interface",
+                    &[
+                        ("name", Some(name)),
+                        ("manual_name", manual_name.as_deref()),
+                        ("cap_description", cap_desc.as_deref()),
+                    ],
+                    formatting,
+                    methods,
+                );
+            }
+            Child::Member(name) => self.element(
+                "member",
+                &[("name", Some(name))],
+                &Formatting::default(),
+                Vec::new(),
+            ),
+            Child::Method(method) => self.method(method),
+            Child::Leaves(tag, leaves) => {
+                self.out.push_str(&format!("<{}>", tag));
+                self.leaves(leaves);
+                self.out.push_str(&format!("</{}>", tag));
+            }
+            Child::Return(return_value) => {
+                self.out.push_str("<return>");
+                for ret in return_value.iter() {
+                    match ret {
+                        Return::ErrorEnumDesc => self.out.push_str("<errorenumdesc/>"),
+                        Return::Leaves(leaf) => self.leaves(std::slice::from_ref(leaf)),
+                    }
+                }
+                self.out.push_str("</return>");
+            }
+            Child::CapParam(cap_param) => self.element(
+                "cap_param",
+                &[("append_description", Some(cap_param.append_description()))],
+                &cap_param.formatting,
+                Vec::new(),
+            ),
+            Child::Param(param) => {
+                let (attribute, leaves) =
+                    split_description(param.description(), param.description_attribute);
+                let mut children = Vec::new();
+                if !leaves.is_empty() {
+                    children.push(Child::Leaves("description", leaves));
+                }
+                children.extend(param.errors().iter().map(Child::Error));
+                self.element(
+                    "param",
+                    &[
+                        ("dir", Some(param.dir())),
+                        ("name", Some(param.name())),
+                        ("type", Some(param.typ())),
+                        ("description", attribute),
+                    ],
+                    &param.formatting,
+                    children,
+                );
+            }
+            Child::Error(error) => {
+                let (attribute, leaves) =
+                    split_description(error.description(), error.description_attribute);
+                let mut children = Vec::new();
+                if !leaves.is_empty() {
+                    children.push(Child::Leaves("description", leaves));
+                }
+                self.element(
+                    "error",
+                    &[("name", Some(error.name())), ("description", attribute)],
+                    &error.formatting,
+                    children,
+                );
+            }
+        }
+    }
+
+    fn method(&mut self, method: &Method) {
+        let mut children = Vec::new();
+        if !method.brief.is_empty() {
+            children.push(Child::Leaves("brief", &method.brief));
+        }
+        if !method.description.is_empty() {
+            children.push(Child::Leaves("description", &method.description));
+        }
+        if !method.return_value.is_empty() {
+            children.push(Child::Return(&method.return_value));
+        }
+        if let Some(cap_param) = &method.cap_param {
+            children.push(Child::CapParam(cap_param));
+        }
+        children.extend(method.params.iter().map(Child::Param));
+        children.extend(method.errors.iter().map(Child::Error));
+        self.element(
+            "method",
+            &[
+                ("id", Some(&method.id)),
+                ("name", Some(&method.name)),
+                ("condition", method.condition.as_deref()),
+                ("manual_name", method.manual_name.as_deref()),
+                ("manual_label", method.manual_label.as_deref()),
+            ],
+            &method.formatting,
+            children,
+        );
+    }
+
+    fn leaves(&mut self, leaves: &[DocLeaf]) {
+        for leaf in leaves {
+            match leaf {
+                DocLeaf::Leaf(leaf, _) => self.leaf(leaf),
+                DocLeaf::DocRef(leaves, _) => {
+                    self.out.push_str("<docref>");
+                    for leaf in leaves {
+                        self.leaf(leaf);
+                    }
+                    self.out.push_str("</docref>");
+                }
+            }
+        }
+    }
+
+    fn leaf(&mut self, leaf: &LeafNode) {
+        let (tag, attribute, value) = match leaf {
+            LeafNode::PCData(text) => {
+                self.out
+                    .push_str(&text.replace('&', "&amp;").replace('<', "&lt;"));
+                return;
+            }
+            LeafNode::TextTT(text) => ("texttt", "text", text),
+            LeafNode::AutoRef { label } => ("autoref", "label", label),
+            LeafNode::ShortRef { sec } => ("shortref", "sec", sec),
+            LeafNode::Obj { name } => ("obj", "name", name),
+        };
+        self.out.push('<');
+        self.out.push_str(tag);
+        self.out.push(' ');
+        self.attribute(attribute, value);
+        self.out.push_str("/>");
+    }
+
+    fn attribute(&mut self, name: &str, value: &str) {
+        let value = value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('"', "&quot;");
+        self.out.push_str(&format!("{}=\"{}\"", name, value));
+    }
+}
+
+// The `description` attribute, and the leaves of the `<description>` element.
+fn split_description(description: &[DocLeaf], attribute: bool) -> (Option<&str>, &[DocLeaf]) {
+    match description {
+        [DocLeaf::Leaf(LeafNode::PCData(text), _), rest @ ..] if attribute => (Some(text), rest),
+        leaves => (None, leaves),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use std::path::Path;
+
+    const XML: &str = r#"<?xml version="1.0" ?>
+<!--
+     Copyright 2020, Data61, CSIRO (ABN 41 687 119 230)
+
+     SPDX-License-Identifier: BSD-2-Clause
+-->
+
+<api name="ObjectApi" label_prefix="seL4_">
+    <struct name="seL4_UserContext">
+        <member name="pc"/>
+        <member name="sp"/>
+    </struct>
+
+    <!-- Untyped -->
+    <interface name="seL4_Untyped" manual_name="Untyped" cap_description="Capability to the untyped memory.">
+        <method id="UntypedRetype" name="Retype" manual_label="untyped_retype">
+            <brief>
+                Retype an untyped object
+            </brief>
+            <description>
+                See <shortref sec="kernmemalloc"/> and <autoref label="sec:untyped"/>,
+                <docref>the <texttt text="seL4_Untyped_Retype"/> &amp; &lt;friends></docref>.
+            </description>
+            <return>
+                A return value of <texttt text="0"/> indicates success. <errorenumdesc/>
+            </return>
+            <cap_param append_description="Must be an untyped capability."/>
+            <param dir="in" name="type" type="seL4_Word"
+                description="The seL4 object type that we are retyping to."/>
+            <!-- The destination -->
+            <param dir="in" name="root" type="seL4_CNode" description="CPTR to the CNode at the root of the destination &quot;CSpace&quot;.">
+                <description> More on the root.</description>
+                <error name="seL4_FailedLookup"/>
+            </param>
+            <error name="seL4_InvalidArgument">
+                <description>
+                    The <texttt text="type"/> is not a valid object type.
+                </description>
+            </error>
+        </method>
+        <method id="UntypedOther" name="Other"
+            condition="defined(CONFIG_KERNEL_MCS)" ></method>
+    </interface>
+</api>
+"#;
+
+    fn methods(api: &mut Api) -> &mut Vec<Method> {
+        match &mut api.children[1] {
+            ApiElement::Interface { methods, .. } => methods,
+            _ => panic!("expected an interface"),
+        }
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), TestError> {
+        let api = Api::parse(Path::new("sel4.xml"), XML)?;
+        assert_eq!(api.to_xml(), XML);
+        Ok(())
+    }
+
+    #[test]
+    fn removed_children() -> Result<(), TestError> {
+        // The comment before the second param stays with it.
+        let mut api = Api::parse(Path::new("sel4.xml"), XML)?;
+        methods(&mut api)[0].params.remove(0);
+        assert!(api.to_xml().contains(
+            r#"<cap_param append_description="Must be an untyped capability."/>
+            <!-- The destination -->
+            <param dir="in" name="root""#
+        ));
+
+        // And goes with it.
+        let mut api = Api::parse(Path::new("sel4.xml"), XML)?;
+        methods(&mut api)[0].params.remove(1);
+        let xml = api.to_xml();
+        assert!(!xml.contains("<!-- The destination -->"));
+        assert!(xml.contains(
+            r#"description="The seL4 object type that we are retyping to."/>
+            <error name="seL4_InvalidArgument">"#
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn edits() -> Result<(), TestError> {
+        let mut api = Api::parse(Path::new("sel4.xml"), XML)?;
+        let methods = methods(&mut api);
+        methods[0].manual_label = None;
+        methods[0].name = "Retype2".to_string();
+        methods[0].params.remove(0);
+        methods[1].condition = None;
+        let mut added = methods[0].clone();
+        added.id = "UntypedAdded".to_string();
+        added.manual_name = Some("Added & more".to_string());
+        added.formatting = Formatting::default();
+        added.brief.clear();
+        added.description.clear();
+        added.return_value.clear();
+        added.cap_param = None;
+        added.params.clear();
+        methods.push(added);
+
+        let xml = api.to_xml();
+        assert!(xml.contains(r#"<method id="UntypedRetype" name="Retype2">"#));
+        assert!(!xml.contains("description=\"The seL4 object type"));
+        assert!(xml.contains("<method id=\"UntypedOther\" name=\"Other\" ></method>"));
+        assert!(xml.contains(
+            r#"        <method id="UntypedAdded" name="Retype2" manual_name="Added &amp; more">
+            <error name="seL4_InvalidArgument">"#
+        ));
+        assert!(xml.ends_with("        </method>\n    </interface>\n</api>\n"));
+
+        // What is written parses back to the same.
+        let reparsed = Api::parse(Path::new("sel4.xml"), &xml)?;
+        assert_eq!(reparsed.to_xml(), xml);
+
+        // Elements built by hand are indented.
+        let api = Api {
+            position: Position::default(),
+            name: None,
+            label_prefix: None,
+            children: vec![ApiElement::StructElem {
+                name: "seL4_Pair".to_string(),
+                members: vec!["first".to_string()],
+                formatting: Formatting::default(),
+            }],
+            formatting: Formatting::default(),
+        };
+        assert_eq!(
+            api.to_xml(),
+            "<?xml version=\"1.0\" ?>\n<api>\n    <struct name=\"seL4_Pair\">\n        \
+             <member name=\"first\"/>\n    </struct>\n</api>\n"
+        );
+        Ok(())
+    }
+}
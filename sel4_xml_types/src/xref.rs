@@ -0,0 +1,250 @@
+use crate::invocations::{Api, ApiElement, DocLeaf, LeafNode, Method, Position, Return};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use thiserror::Error;
+
+//
+// Checks the `<autoref label=.../>` and `<shortref sec=.../>` of the IDL
+// documentation against the labels of the manual.
+//
+// An autoref ends up as `\autoref{label}` in the LaTeX of the manual and a
+// shortref as `\ref{sec:sec}`, the labels of sections being written
+// `\label{sec:kernmemalloc}`. A dangling one is only noticed as a `??` in
+// the built pdf.
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefKind {
+    /// `<autoref label=.../>`
+    AutoRef,
+    /// `<shortref sec=.../>`
+    ShortRef,
+}
+
+impl fmt::Display for RefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefKind::AutoRef => write!(f, "autoref"),
+            RefKind::ShortRef => write!(f, "shortref"),
+        }
+    }
+}
+
+/// A reference in the documentation of a method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Xref {
+    pub kind: RefKind,
+    /// The attribute as written, `sec` of a shortref has no `sec:` prefix.
+    pub target: String,
+    /// The id of the documented method.
+    pub method: String,
+    /// Of the enclosing `<docref>` for references within one.
+    pub position: Position,
+}
+
+impl Xref {
+    fn from_leaf(leaf: &LeafNode, method: &Method, position: Position) -> Option<Xref> {
+        let (kind, target) = match leaf {
+            LeafNode::AutoRef { label } => (RefKind::AutoRef, label),
+            LeafNode::ShortRef { sec } => (RefKind::ShortRef, sec),
+            _ => return None,
+        };
+        Some(Xref {
+            kind,
+            target: target.clone(),
+            method: method.id.clone(),
+            position,
+        })
+    }
+
+    /// The label of the manual referred to e.g. `sec:kernmemalloc` for
+    /// `<shortref sec="kernmemalloc"/>`
+    pub fn label(&self) -> String {
+        match self.kind {
+            RefKind::AutoRef => self.target.clone(),
+            RefKind::ShortRef => format!("sec:{}", self.target),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "{}: {} to unknown label '{}' in the documentation of '{}'",
+    .0.position, .0.kind, .0.label(), .0.method
+)]
+pub struct DanglingRef(pub Xref);
+
+/// The labels of the manual.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelIndex {
+    labels: BTreeSet<String>,
+}
+
+impl LabelIndex {
+    pub fn new() -> LabelIndex {
+        LabelIndex::default()
+    }
+
+    /// Reads the labels from LaTeX, the `\label{...}` of the sources or the
+    /// `\newlabel{...}` of a `.aux` file. Text without either is taken to
+    /// be a list of labels, one per line.
+    pub fn parse(text: &str) -> LabelIndex {
+        let mut index = LabelIndex::new();
+        for (i, _) in text.match_indices("label{") {
+            let name = &text[i + "label{".len()..];
+            if let Some(end) = name.find('}') {
+                index.insert(&name[..end]);
+            }
+        }
+        if index.labels.is_empty() {
+            for line in text.lines().map(str::trim) {
+                if !line.is_empty() && !line.starts_with('%') {
+                    index.insert(line);
+                }
+            }
+        }
+        index
+    }
+
+    pub fn insert(&mut self, label: &str) {
+        self.labels.insert(label.to_string());
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.labels.contains(label)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.labels.iter().map(String::as_str)
+    }
+
+    /// The references of `api` to labels not in the index.
+    pub fn resolve(&self, api: &Api) -> Vec<DanglingRef> {
+        references(api)
+            .into_iter()
+            .filter(|xref| !self.contains(&xref.label()))
+            .map(DanglingRef)
+            .collect()
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for LabelIndex {
+    fn from_iter<I: IntoIterator<Item = S>>(labels: I) -> LabelIndex {
+        let mut index = LabelIndex::new();
+        for label in labels {
+            index.insert(label.as_ref());
+        }
+        index
+    }
+}
+
+/// Every `autoref` and `shortref` in the documentation of `api`, in
+/// document order.
+pub fn references(api: &Api) -> Vec<Xref> {
+    let mut xrefs = Vec::new();
+    for child in &api.children {
+        if let ApiElement::Interface { methods, .. } = child {
+            for method in methods {
+                method_references(method, &mut xrefs);
+            }
+        }
+    }
+    xrefs
+}
+
+fn method_references(method: &Method, xrefs: &mut Vec<Xref>) {
+    let mut leaves: Vec<&DocLeaf> = Vec::new();
+    leaves.extend(&method.brief);
+    leaves.extend(&method.description);
+    leaves.extend(method.return_value.iter().filter_map(|ret| match ret {
+        Return::Leaves(leaf) => Some(leaf),
+        Return::ErrorEnumDesc => None,
+    }));
+    for param in &method.params {
+        leaves.extend(param.description());
+        for error in param.errors() {
+            leaves.extend(error.description());
+        }
+    }
+    for error in &method.errors {
+        leaves.extend(error.description());
+    }
+
+    for leaf in leaves {
+        match leaf {
+            DocLeaf::Leaf(leaf, position) => xrefs.extend(Xref::from_leaf(leaf, method, *position)),
+            DocLeaf::DocRef(docref, position) => xrefs.extend(
+                docref
+                    .iter()
+                    .filter_map(|leaf| Xref::from_leaf(leaf, method, *position)),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use std::path::Path;
+
+    #[test]
+    fn resolve() -> Result<(), TestError> {
+        let xml = r#"<api>
+    <interface name="seL4_TCB">
+        <method id="TCBSuspend" name="Suspend">
+            <brief>See <autoref label="sec:threads"/></brief>
+            <description>
+                <docref>See <shortref sec="thread_deactivation"/></docref>
+            </description>
+            <param dir="in" name="a" type="seL4_Word">
+                <description>See <autoref label="sec:faults"/></description>
+            </param>
+            <error name="seL4_IllegalOperation">
+                <description>See <shortref sec="errors"/></description>
+            </error>
+        </method>
+    </interface>
+</api>"#;
+        let api = Api::parse(Path::new("sel4.xml"), xml)?;
+        let xrefs: Vec<_> = references(&api)
+            .into_iter()
+            .map(|xref| (xref.kind, xref.target, xref.position.line))
+            .collect();
+        assert_eq!(
+            xrefs,
+            [
+                (RefKind::AutoRef, "sec:threads".to_string(), 4),
+                (RefKind::ShortRef, "thread_deactivation".to_string(), 6),
+                (RefKind::AutoRef, "sec:faults".to_string(), 9),
+                (RefKind::ShortRef, "errors".to_string(), 12),
+            ]
+        );
+
+        let index = LabelIndex::parse(
+            r"\section{Threads}\label{sec:threads}
+\newlabel{sec:thread_deactivation}{{6.1}{32}}
+\label{sec:errors}",
+        );
+        let dangling = index.resolve(&api);
+        assert_eq!(dangling.len(), 1);
+        assert_eq!(
+            dangling[0].to_string(),
+            "9:34: autoref to unknown label 'sec:faults' in the documentation of 'TCBSuspend'"
+        );
+
+        let index = LabelIndex::parse("sec:threads\nsec:faults\n");
+        let dangling: Vec<_> = index
+            .resolve(&api)
+            .into_iter()
+            .map(|d| d.0.label())
+            .collect();
+        assert_eq!(dangling, ["sec:thread_deactivation", "sec:errors"]);
+
+        // The raw name of a section isn't its label.
+        let index = LabelIndex::parse("sec:threads\nsec:faults\nthread_deactivation\nerrors\n");
+        assert_eq!(index.resolve(&api).len(), 2);
+        Ok(())
+    }
+}